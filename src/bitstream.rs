@@ -0,0 +1,90 @@
+//! A bit-level writer used to build up the DEFLATE bitstream.
+//!
+//! DEFLATE packs codes of varying, non-byte-aligned widths (Huffman codes, extra bits, block
+//! headers) into a stream that is read least-significant-bit first within each byte. This module
+//! provides a small buffer that accumulates bits and flushes complete bytes to the underlying
+//! writer as they become available.
+
+use std::io::{self, Write};
+
+/// A sink for bits, writing completed bytes to an underlying `Write` implementation
+/// least-significant-bit first, matching the bit order used by the DEFLATE format.
+pub trait BitWriter {
+    /// Write the lowest `num_bits` bits of `bits` to the stream.
+    fn write_bits(&mut self, bits: u16, num_bits: u8) -> io::Result<()>;
+    /// Number of bits currently sitting in the internal buffer, not yet written out.
+    fn pending_bits(&self) -> u8;
+    /// Pad the current byte with zeroes and flush any complete bytes to the underlying writer.
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// The default `BitWriter` implementation, wrapping any `Write`.
+pub struct LsbWriter<W: Write> {
+    bit_buffer: u32,
+    bits_in_buffer: u8,
+    w: W,
+}
+
+impl<W: Write> LsbWriter<W> {
+    pub fn new(w: W) -> LsbWriter<W> {
+        LsbWriter {
+            bit_buffer: 0,
+            bits_in_buffer: 0,
+            w: w,
+        }
+    }
+
+    /// Consume the writer, returning the wrapped writer.
+    ///
+    /// Any bits still sitting in the buffer that haven't been flushed are lost, so callers should
+    /// call `flush` first if they care about that data.
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+
+    pub fn inner_mut(&mut self) -> &mut W {
+        &mut self.w
+    }
+}
+
+impl<W: Write> BitWriter for LsbWriter<W> {
+    fn write_bits(&mut self, bits: u16, num_bits: u8) -> io::Result<()> {
+        debug_assert!(num_bits <= 16);
+        self.bit_buffer |= ((bits as u32) & ((1u32 << num_bits) - 1)) << self.bits_in_buffer;
+        self.bits_in_buffer += num_bits;
+        while self.bits_in_buffer >= 8 {
+            self.w.write_all(&[(self.bit_buffer & 0xff) as u8])?;
+            self.bit_buffer >>= 8;
+            self.bits_in_buffer -= 8;
+        }
+        Ok(())
+    }
+
+    fn pending_bits(&self) -> u8 {
+        self.bits_in_buffer
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.bits_in_buffer > 0 {
+            self.w.write_all(&[(self.bit_buffer & 0xff) as u8])?;
+            self.bit_buffer = 0;
+            self.bits_in_buffer = 0;
+        }
+        self.w.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_bits() {
+        let mut writer = LsbWriter::new(Vec::new());
+        writer.write_bits(0b1, 1).unwrap();
+        writer.write_bits(0b0, 1).unwrap();
+        writer.write_bits(0b101, 3).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.into_inner(), vec![0b0001_0101]);
+    }
+}
@@ -0,0 +1,96 @@
+//! A chained hash table over 3-byte sequences, used by the LZ77 matcher to find candidate
+//! earlier positions in the sliding window that a match could be copied from.
+
+/// Size in bits of the hash table used to index 3-byte sequences.
+const HASH_BITS: usize = 15;
+const HASH_SIZE: usize = 1 << HASH_BITS;
+const HASH_MASK: u32 = (HASH_SIZE - 1) as u32;
+
+/// The size of the DEFLATE sliding window.
+pub const WINDOW_SIZE: usize = 32768;
+pub const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+
+fn hash3(b0: u8, b1: u8, b2: u8) -> usize {
+    (((b0 as u32) << 10) ^ ((b1 as u32) << 5) ^ (b2 as u32)) as usize & (HASH_MASK as usize)
+}
+
+/// Chains of equal-hash positions within the last `WINDOW_SIZE` bytes seen.
+///
+/// `head` maps a hash value to the most recently inserted position with that hash, and `prev`
+/// links each inserted position back to the previous one sharing the same hash, forming a chain
+/// that can be walked from most to least recent. Both store positions as `actual position + 1` so
+/// that `0` can mean "no entry".
+pub struct ChainedHashTable {
+    head: Vec<u32>,
+    prev: Vec<u32>,
+}
+
+impl ChainedHashTable {
+    pub fn new() -> ChainedHashTable {
+        ChainedHashTable {
+            head: vec![0u32; HASH_SIZE],
+            prev: vec![0u32; WINDOW_SIZE],
+        }
+    }
+
+    /// Create a hash table pre-filled as though the last up-to-`WINDOW_SIZE` bytes of
+    /// `dictionary` were already the start of the sliding window, without those bytes being
+    /// output. Used to seed the LZ77 matcher with a preset dictionary.
+    pub fn from_dictionary(dictionary: &[u8]) -> ChainedHashTable {
+        let mut table = ChainedHashTable::new();
+        let start = dictionary.len().saturating_sub(WINDOW_SIZE);
+        let window = &dictionary[start..];
+        if window.len() >= 3 {
+            for i in 0..window.len() - 2 {
+                table.add_hash_value(i, window[i], window[i + 1], window[i + 2]);
+            }
+        }
+        table
+    }
+
+    pub fn get_hash(&self, b0: u8, b1: u8, b2: u8) -> usize {
+        hash3(b0, b1, b2)
+    }
+
+    /// Record that a 3-byte sequence starting at `position` hashes to `hash3(b0, b1, b2)`.
+    pub fn add_hash_value(&mut self, position: usize, b0: u8, b1: u8, b2: u8) {
+        let hash = hash3(b0, b1, b2);
+        let previous_head = self.head[hash];
+        self.prev[position & WINDOW_MASK] = previous_head;
+        self.head[hash] = position as u32 + 1;
+    }
+
+    /// The most recently inserted position (if any) with the given hash.
+    pub fn get_head(&self, hash: usize) -> Option<usize> {
+        match self.head[hash] {
+            0 => None,
+            p => Some(p as usize - 1),
+        }
+    }
+
+    /// The position inserted just before `position` that shares its hash, if any.
+    pub fn get_prev(&self, position: usize) -> Option<usize> {
+        match self.prev[position & WINDOW_MASK] {
+            0 => None,
+            p => Some(p as usize - 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_find() {
+        let mut table = ChainedHashTable::new();
+        let data = b"abcabc";
+        table.add_hash_value(0, data[0], data[1], data[2]);
+        table.add_hash_value(3, data[3], data[4], data[5]);
+
+        let hash = table.get_hash(data[3], data[4], data[5]);
+        assert_eq!(table.get_head(hash), Some(3));
+        assert_eq!(table.get_prev(3), Some(0));
+        assert_eq!(table.get_prev(0), None);
+    }
+}
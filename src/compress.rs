@@ -0,0 +1,643 @@
+//! Incremental compression through the standard `Write` trait.
+//!
+//! `lib.rs`'s `deflate_bytes`/`deflate_bytes_zlib` need the whole input up front. The encoders
+//! here instead buffer input as it's written and compress completed windows of it as they become
+//! available, so they can sit in the middle of an I/O pipeline instead of requiring the caller to
+//! materialize the entire payload in memory first.
+
+use std::io::{self, Write};
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+#[cfg(feature = "gzip")]
+use checksum::Crc32Checksum;
+use checksum::{Adler32Checksum, NoChecksum, RollingChecksum};
+use compression_options::Compression;
+#[cfg(feature = "gzip")]
+use gzip::{write_trailer, GzBuilder};
+use encoder_state::{EncoderState, BType};
+use huffman_lengths::{remove_trailing_zeroes, write_huffman_lengths, MIN_NUM_DISTANCES,
+                      MIN_NUM_LITERALS_AND_LENGTHS};
+use huffman_table::{HuffmanTable, MAX_CODE_LENGTH, FIXED_CODE_LENGTHS, FIXED_CODE_LENGTHS_DISTANCE};
+use length_encode::huffman_lengths_from_frequency;
+use chained_hash_table::WINDOW_SIZE;
+use lz77::{create_buffer_with_dictionary_at_offset, lz77_compress_block, LZ77State, MAX_BLOCK_LENGTH};
+use output_writer::DynamicWriter;
+use stored_block::{compress_block_stored, MAX_STORED_BLOCK_LENGTH, STORED_FIRST_BYTE,
+                   STORED_FIRST_BYTE_FINAL};
+use zlib::{write_zlib_header, write_zlib_header_with_dictionary, CompressionLevel};
+use block_type_for_block;
+
+/// Controls how much a streaming encoder forces out of its internal buffers on a call to
+/// `flush_with_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flush {
+    /// Don't force anything out; let input keep accumulating until a full window is ready to be
+    /// compressed efficiently. This is what plain calls to `write` use.
+    None,
+    /// Compress and emit everything buffered so far as one or more blocks, then write an empty,
+    /// non-final stored block (a 3-bit header followed by the bytes `00 00 FF FF` once
+    /// byte-aligned) so a decoder reading the stream can resynchronize at this exact point.
+    Sync,
+    /// Compress and emit everything buffered, mark the final block as final, and flush any
+    /// partial byte left in the bitstream. Called automatically on `Drop` if not done already.
+    Finish,
+}
+
+/// A DEFLATE encoder that implements `Write`, compressing input incrementally instead of all at
+/// once.
+pub struct DeflateEncoder<W: Write> {
+    // `None` once the stream has been finished and its writer handed back via `into_inner`.
+    state: Option<EncoderState<W>>,
+    lz77_state: LZ77State,
+    input_buffer: Vec<u8>,
+    lz77_writer: DynamicWriter,
+    dictionary: Vec<u8>,
+    // How many bytes have been permanently dropped off the front of `input_buffer` because they
+    // fell out of the sliding window; see `trim_input_buffer`.
+    consumed_offset: usize,
+    level: Compression,
+}
+
+impl<W: Write> DeflateEncoder<W> {
+    pub fn new(writer: W, level: Compression) -> DeflateEncoder<W> {
+        DeflateEncoder::with_dictionary(writer, level, &[])
+    }
+
+    /// As `new`, but seeding the LZ77 match search with a preset dictionary.
+    pub(crate) fn with_dictionary(writer: W, level: Compression, dictionary: &[u8]) -> DeflateEncoder<W> {
+        DeflateEncoder {
+            state: Some(EncoderState::new(HuffmanTable::empty(), writer)),
+            lz77_state: LZ77State::with_dictionary(&[], dictionary, level.to_options()),
+            input_buffer: Vec::new(),
+            lz77_writer: DynamicWriter::new(),
+            dictionary: dictionary.to_vec(),
+            consumed_offset: 0,
+            level: level,
+        }
+    }
+
+    fn state_mut(&mut self) -> &mut EncoderState<W> {
+        self.state.as_mut().expect("DeflateEncoder used after being finished")
+    }
+
+    /// Pick a `BType` for this block and write it out, the same way `lib.rs`'s
+    /// `compress_data_dynamic` does for the one-shot encoders: `Compression::none()` always
+    /// stores, other levels pick whichever of stored/fixed/dynamic huffman the LZ77 symbols
+    /// gathered for this block estimate as cheapest.
+    ///
+    /// `block_start`/`block_length` locate the raw bytes of this block within `input_buffer`, for
+    /// `BType::NoCompression`; the LZ77 symbols already buffered in `self.lz77_writer` are used
+    /// for the other two.
+    fn write_block(&mut self, block_start: usize, block_length: usize, final_block: bool) -> io::Result<()> {
+        let block_type = if self.level.is_none() {
+            BType::NoCompression
+        } else {
+            block_type_for_block(block_length, &self.lz77_writer)
+        };
+
+        match block_type {
+            BType::NoCompression => {
+                // `MAX_BLOCK_LENGTH` (65536) is one byte longer than a stored block can hold
+                // (`MAX_STORED_BLOCK_LENGTH`, 65535, since its length is a 16-bit field), so a
+                // full-sized block forced to `NoCompression` by `Compression::none()` still needs
+                // splitting into several stored blocks, same as `compress_data_dynamic`'s
+                // `level.is_none()` case.
+                use bitstream::BitWriter;
+                let start = block_start - self.consumed_offset;
+                let block_data = self.input_buffer[start..start + block_length].to_vec();
+                let chunks: Vec<&[u8]> = if block_data.is_empty() {
+                    vec![&block_data[..]]
+                } else {
+                    block_data.chunks(MAX_STORED_BLOCK_LENGTH).collect()
+                };
+                let last = chunks.len() - 1;
+                let state = self.state_mut();
+                for (i, chunk) in chunks.into_iter().enumerate() {
+                    let header = if final_block && i == last {
+                        STORED_FIRST_BYTE_FINAL
+                    } else {
+                        STORED_FIRST_BYTE
+                    };
+                    try!(state.writer.write_bits(header.into(), 3));
+                    try!(state.flush());
+                    try!(compress_block_stored(chunk, &mut state.writer));
+                }
+            }
+            BType::FixedHuffman => {
+                let pairs = self.lz77_writer.get_buffer().to_vec();
+                let state = self.state_mut();
+                state.update_huffman_table(&FIXED_CODE_LENGTHS, &FIXED_CODE_LENGTHS_DISTANCE)
+                    .unwrap();
+                try!(state.write_start_of_block(true, final_block));
+                for ld in pairs {
+                    try!(state.write_ldpair(ld));
+                }
+            }
+            BType::DynamicHuffman => {
+                let (l_lengths, d_lengths) = {
+                    let (l_freqs, d_freqs) = self.lz77_writer.get_frequencies();
+                    (huffman_lengths_from_frequency(
+                         remove_trailing_zeroes(l_freqs, MIN_NUM_LITERALS_AND_LENGTHS),
+                         MAX_CODE_LENGTH),
+                     huffman_lengths_from_frequency(
+                         remove_trailing_zeroes(d_freqs, MIN_NUM_DISTANCES),
+                         MAX_CODE_LENGTH))
+                };
+
+                // Copy the buffered symbols out before borrowing `self.state`: `LDPair` is `Copy`,
+                // and this lets the loop below write through `state` without holding a second
+                // borrow of `self`.
+                let pairs = self.lz77_writer.get_buffer().to_vec();
+
+                let state = self.state_mut();
+                try!(state.write_start_of_block(false, final_block));
+                try!(write_huffman_lengths(&l_lengths, &d_lengths, &mut state.writer));
+                try!(state.update_huffman_table(&l_lengths, &d_lengths));
+                for ld in pairs {
+                    try!(state.write_ldpair(ld));
+                }
+            }
+        }
+
+        self.lz77_writer.clear();
+        Ok(())
+    }
+
+    /// Run the LZ77 step over one block's worth of the buffered input and write it out.
+    fn compress_one_block(&mut self, final_block: bool) -> io::Result<()> {
+        let buffer = create_buffer_with_dictionary_at_offset(&self.input_buffer,
+                                                              &self.dictionary,
+                                                              self.consumed_offset);
+        let block_start = self.lz77_state.position();
+        lz77_compress_block(&buffer,
+                             &mut self.lz77_state,
+                             &mut self.lz77_writer,
+                             &mut NoChecksum::new());
+        let block_length = self.lz77_state.position() - block_start;
+        // `lz77_compress_block` can close a block early (see `lz77::should_split_block`), so only
+        // a block that used up everything pending is marked as final.
+        let final_block = final_block && self.pending() == 0;
+        try!(self.write_block(block_start, block_length, final_block));
+        self.trim_input_buffer();
+        Ok(())
+    }
+
+    /// Drop whatever prefix of `input_buffer` the LZ77 matcher can no longer reach (everything
+    /// more than `WINDOW_SIZE` bytes behind the current position), so a long-lived encoder doesn't
+    /// retain the entire history of everything ever written to it.
+    fn trim_input_buffer(&mut self) {
+        let keep_from = self.lz77_state.position().saturating_sub(WINDOW_SIZE);
+        if keep_from > self.consumed_offset {
+            let drop_count = keep_from - self.consumed_offset;
+            self.input_buffer.drain(..drop_count);
+            self.consumed_offset += drop_count;
+        }
+    }
+
+    fn pending(&self) -> usize {
+        self.input_buffer.len() + self.consumed_offset - self.lz77_state.position()
+    }
+
+    /// Compress as many full `MAX_BLOCK_LENGTH` windows of buffered input as are available,
+    /// leaving anything short of a full window buffered for later.
+    fn compress_available(&mut self) -> io::Result<()> {
+        while self.pending() >= MAX_BLOCK_LENGTH {
+            try!(self.compress_one_block(false));
+        }
+        Ok(())
+    }
+
+    /// Compress everything currently buffered, however little, marking the last block produced
+    /// as final if `final_block` is set.
+    fn compress_remaining(&mut self, final_block: bool) -> io::Result<()> {
+        if final_block && self.pending() == 0 {
+            // Nothing left to compress, but the stream still needs a final block.
+            return self.compress_one_block(true);
+        }
+        while self.pending() > 0 {
+            try!(self.compress_one_block(final_block));
+        }
+        Ok(())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if self.state.is_none() {
+            return Ok(());
+        }
+        try!(self.compress_remaining(true));
+        try!(self.state_mut().flush());
+        Ok(())
+    }
+
+    /// Flush buffered input according to `mode`. See `Flush` for what each mode does.
+    pub fn flush_with_mode(&mut self, mode: Flush) -> io::Result<()> {
+        match mode {
+            Flush::None => self.compress_available(),
+            Flush::Sync => {
+                use bitstream::BitWriter;
+                try!(self.compress_remaining(false));
+                let state = self.state_mut();
+                try!(state.writer.write_bits(STORED_FIRST_BYTE as u16, 3));
+                try!(state.flush());
+                compress_block_stored(&[], &mut state.writer)
+            }
+            Flush::Finish => self.try_finish(),
+        }
+    }
+
+    /// Finish the stream (as `flush_with_mode(Flush::Finish)` would) and return the underlying
+    /// writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        try!(self.try_finish());
+        Ok(self.state.take().expect("just finished").writer.into_inner())
+    }
+}
+
+impl<W: Write> Write for DeflateEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.input_buffer.extend_from_slice(buf);
+        try!(self.compress_available());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_with_mode(Flush::Sync)
+    }
+}
+
+impl<W: Write> Drop for DeflateEncoder<W> {
+    fn drop(&mut self) {
+        // Best-effort: there's no way to report a write error from a destructor, and a caller
+        // that cares about catching one should call `into_inner`/`flush_with_mode` explicitly.
+        let _ = self.try_finish();
+    }
+}
+
+/// A DEFLATE encoder that wraps its output in a zlib (RFC 1950) header and trailer, compressing
+/// input incrementally as it's written.
+pub struct ZlibEncoder<W: Write> {
+    // `None` once the stream has been finished and its writer handed back (by `into_inner`, or,
+    // on `Drop`, discarded after the trailer has been written).
+    inner: Option<DeflateEncoder<W>>,
+    checksum: Adler32Checksum,
+}
+
+impl<W: Write> ZlibEncoder<W> {
+    pub fn new(mut writer: W, level: Compression) -> io::Result<ZlibEncoder<W>> {
+        try!(write_zlib_header(&mut writer, CompressionLevel::from_compression(level)));
+        Ok(ZlibEncoder {
+            inner: Some(DeflateEncoder::new(writer, level)),
+            checksum: Adler32Checksum::new(),
+        })
+    }
+
+    /// As `new`, but seeding the LZ77 match search with a preset dictionary. Sets the `FDICT` flag
+    /// in the zlib header and writes the dictionary's Adler32 immediately after it, as required by
+    /// RFC 1950 section 2.2.
+    pub fn new_with_dictionary(mut writer: W,
+                                level: Compression,
+                                dictionary: &[u8])
+                                -> io::Result<ZlibEncoder<W>> {
+        let mut dictionary_checksum = Adler32Checksum::new();
+        dictionary_checksum.update_from_slice(dictionary);
+        try!(write_zlib_header_with_dictionary(&mut writer,
+                                                CompressionLevel::from_compression(level),
+                                                dictionary_checksum.current_hash()));
+        Ok(ZlibEncoder {
+            inner: Some(DeflateEncoder::with_dictionary(writer, level, dictionary)),
+            checksum: Adler32Checksum::new(),
+        })
+    }
+
+    fn inner_mut(&mut self) -> &mut DeflateEncoder<W> {
+        self.inner.as_mut().expect("ZlibEncoder used after being finished")
+    }
+
+    pub fn flush_with_mode(&mut self, mode: Flush) -> io::Result<()> {
+        self.inner_mut().flush_with_mode(mode)
+    }
+
+    /// Finish the stream and write the Adler32 trailer, without consuming `self`. Returns `None`
+    /// if the stream was already finished (shared by `into_inner` and `Drop`, so `Drop` on an
+    /// encoder that already went through `into_inner` doesn't write the trailer a second time).
+    fn finish(&mut self) -> io::Result<Option<W>> {
+        match self.inner.take() {
+            Some(inner) => {
+                let hash = self.checksum.current_hash();
+                let mut writer = try!(inner.into_inner());
+                try!(writer.write_u32::<BigEndian>(hash));
+                Ok(Some(writer))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Finish the stream, write the Adler32 trailer, and return the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        Ok(try!(self.finish()).expect("ZlibEncoder used after being finished"))
+    }
+}
+
+impl<W: Write> Write for ZlibEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.checksum.update_from_slice(buf);
+        self.inner_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner_mut().flush()
+    }
+}
+
+impl<W: Write> Drop for ZlibEncoder<W> {
+    fn drop(&mut self) {
+        // Best-effort, as with `DeflateEncoder`'s own `Drop`: there's no way to report a write
+        // error from a destructor, and a caller that cares about one should call `into_inner`
+        // explicitly.
+        let _ = self.finish();
+    }
+}
+
+/// A DEFLATE encoder that wraps its output in a gzip (RFC 1952) header and trailer, compressing
+/// input incrementally as it's written. Requires the `gzip` feature.
+#[cfg(feature = "gzip")]
+pub struct GzEncoder<W: Write> {
+    // `None` once the stream has been finished and its writer handed back (by `into_inner`, or,
+    // on `Drop`, discarded after the trailer has been written).
+    inner: Option<DeflateEncoder<W>>,
+    checksum: Crc32Checksum,
+    uncompressed_length: u64,
+}
+
+#[cfg(feature = "gzip")]
+impl<W: Write> GzEncoder<W> {
+    pub fn new(mut writer: W, options: &GzBuilder, level: Compression) -> io::Result<GzEncoder<W>> {
+        try!(options.write_header(&mut writer, level));
+        Ok(GzEncoder {
+            inner: Some(DeflateEncoder::new(writer, level)),
+            checksum: Crc32Checksum::new(),
+            uncompressed_length: 0,
+        })
+    }
+
+    fn inner_mut(&mut self) -> &mut DeflateEncoder<W> {
+        self.inner.as_mut().expect("GzEncoder used after being finished")
+    }
+
+    pub fn flush_with_mode(&mut self, mode: Flush) -> io::Result<()> {
+        self.inner_mut().flush_with_mode(mode)
+    }
+
+    /// Finish the stream and write the CRC32/length trailer, without consuming `self`. Returns
+    /// `None` if the stream was already finished (shared by `into_inner` and `Drop`, so `Drop` on
+    /// an encoder that already went through `into_inner` doesn't write the trailer a second time).
+    fn finish(&mut self) -> io::Result<Option<W>> {
+        match self.inner.take() {
+            Some(inner) => {
+                let crc = self.checksum.current_hash();
+                let length = self.uncompressed_length;
+                let mut writer = try!(inner.into_inner());
+                try!(write_trailer(&mut writer, crc, length));
+                Ok(Some(writer))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Finish the stream, write the CRC32/length trailer, and return the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        Ok(try!(self.finish()).expect("GzEncoder used after being finished"))
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<W: Write> Write for GzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.checksum.update_from_slice(buf);
+        self.uncompressed_length += buf.len() as u64;
+        self.inner_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner_mut().flush()
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<W: Write> Drop for GzEncoder<W> {
+    fn drop(&mut self) {
+        // Best-effort, as with `DeflateEncoder`'s own `Drop`: there's no way to report a write
+        // error from a destructor, and a caller that cares about one should call `into_inner`
+        // explicitly.
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::io::Read;
+    use std::rc::Rc;
+
+    use flate2::read::{DeflateDecoder, ZlibDecoder};
+    #[cfg(feature = "gzip")]
+    use flate2::read::GzDecoder;
+
+    fn decompress_to_end(input: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+        DeflateDecoder::new(input).read_to_end(&mut result).unwrap();
+        result
+    }
+
+    fn decompress_zlib(input: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+        ZlibDecoder::new(input).read_to_end(&mut result).unwrap();
+        result
+    }
+
+    fn test_data() -> Vec<u8> {
+        String::from("This is some test data, with some repetition to make it compressible. \
+                      This is some test data, with some repetition to make it compressible.")
+            .into_bytes()
+    }
+
+    /// A `Write` implementation over a shared buffer, so a test can still read what was written
+    /// to a writer after an encoder wrapping it has been dropped.
+    #[derive(Clone)]
+    struct SharedWriter(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedWriter {
+        fn new() -> SharedWriter {
+            SharedWriter(Rc::new(RefCell::new(Vec::new())))
+        }
+    }
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.borrow_mut().flush()
+        }
+    }
+
+    #[test]
+    fn test_deflate_encoder_roundtrip() {
+        let test_data = test_data();
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&test_data).unwrap();
+        let compressed = encoder.into_inner().unwrap();
+
+        assert_eq!(decompress_to_end(&compressed), test_data);
+    }
+
+    #[test]
+    fn test_deflate_encoder_roundtrip_multiple_writes() {
+        // Each `write` call feeds the encoder's buffer independently of the underlying
+        // `MAX_BLOCK_LENGTH` windowing, so splitting the input across many small writes shouldn't
+        // change what comes out the other end.
+        let test_data = test_data();
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        for chunk in test_data.chunks(7) {
+            encoder.write_all(chunk).unwrap();
+        }
+        let compressed = encoder.into_inner().unwrap();
+
+        assert_eq!(decompress_to_end(&compressed), test_data);
+    }
+
+    #[test]
+    fn test_deflate_encoder_flush_sync() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"before the sync point").unwrap();
+        encoder.flush_with_mode(Flush::Sync).unwrap();
+        encoder.write_all(b", after the sync point").unwrap();
+        let compressed = encoder.into_inner().unwrap();
+
+        assert_eq!(decompress_to_end(&compressed),
+                   b"before the sync point, after the sync point".to_vec());
+    }
+
+    #[test]
+    fn test_deflate_encoder_compression_none_uses_stored_blocks() {
+        // Highly repetitive data that dynamic huffman coding would shrink drastically; with
+        // `Compression::none()` every block should be written as `BType::NoCompression` instead,
+        // so the output can only grow (stored block framing overhead) rather than shrink. Also
+        // long enough to exceed `MAX_BLOCK_LENGTH`, exercising a block that needs splitting into
+        // more than one stored block, since a stored block's length is a 16-bit field.
+        let test_data = vec![42u8; 70_000];
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::none());
+        encoder.write_all(&test_data).unwrap();
+        let compressed = encoder.into_inner().unwrap();
+
+        assert!(compressed.len() > test_data.len());
+        assert_eq!(decompress_to_end(&compressed), test_data);
+    }
+
+    #[test]
+    fn test_deflate_encoder_with_dictionary() {
+        let dictionary = b"The quick brown fox jumps over the lazy dog.".to_vec();
+        let mut test_data = dictionary.clone();
+        test_data.extend_from_slice(b" Just kidding, that's the whole sentence.");
+
+        let mut with_dict = DeflateEncoder::with_dictionary(Vec::new(),
+                                                             Compression::default(),
+                                                             &dictionary);
+        with_dict.write_all(&test_data).unwrap();
+        let with_dict = with_dict.into_inner().unwrap();
+
+        let mut without_dict = DeflateEncoder::new(Vec::new(), Compression::default());
+        without_dict.write_all(&test_data).unwrap();
+        let without_dict = without_dict.into_inner().unwrap();
+
+        assert!(with_dict.len() < without_dict.len());
+        assert_eq!(decompress_to_end(&with_dict), test_data);
+    }
+
+    #[test]
+    fn test_zlib_encoder_roundtrip() {
+        let test_data = test_data();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default()).unwrap();
+        encoder.write_all(&test_data).unwrap();
+        let compressed = encoder.into_inner().unwrap();
+
+        assert_eq!(decompress_zlib(&compressed), test_data);
+    }
+
+    #[test]
+    fn test_zlib_encoder_drop_without_into_inner_still_writes_trailer() {
+        let writer = SharedWriter::new();
+        let test_data = test_data();
+        {
+            let mut encoder = ZlibEncoder::new(writer.clone(), Compression::default()).unwrap();
+            encoder.write_all(&test_data).unwrap();
+            // Dropped here without calling `into_inner`: `ZlibDecoder` below only succeeds if the
+            // Adler32 trailer got written anyway.
+        }
+        let compressed = writer.0.borrow().clone();
+
+        assert_eq!(decompress_zlib(&compressed), test_data);
+    }
+
+    #[test]
+    fn test_zlib_encoder_into_inner_then_drop_does_not_duplicate_trailer() {
+        let writer = SharedWriter::new();
+        let test_data = test_data();
+        let encoder = {
+            let mut encoder = ZlibEncoder::new(writer.clone(), Compression::default()).unwrap();
+            encoder.write_all(&test_data).unwrap();
+            encoder
+        };
+        let returned = encoder.into_inner().unwrap();
+        drop(returned);
+
+        let compressed = writer.0.borrow().clone();
+        assert_eq!(decompress_zlib(&compressed), test_data);
+
+        // The decoder stops after the first trailer, so a second one would go unnoticed above;
+        // compare against the output of an encoder that is only ever finished once.
+        let mut reference = ZlibEncoder::new(Vec::new(), Compression::default()).unwrap();
+        reference.write_all(&test_data).unwrap();
+        assert_eq!(compressed, reference.into_inner().unwrap());
+    }
+
+    #[cfg(feature = "gzip")]
+    fn decompress_gzip(input: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+        GzDecoder::new(input).read_to_end(&mut result).unwrap();
+        result
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gz_encoder_roundtrip() {
+        let test_data = test_data();
+        let mut encoder = GzEncoder::new(Vec::new(), &GzBuilder::new(), Compression::default())
+            .unwrap();
+        encoder.write_all(&test_data).unwrap();
+        let compressed = encoder.into_inner().unwrap();
+
+        assert_eq!(decompress_gzip(&compressed), test_data);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gz_encoder_drop_without_into_inner_still_writes_trailer() {
+        let writer = SharedWriter::new();
+        let test_data = test_data();
+        {
+            let mut encoder = GzEncoder::new(writer.clone(), &GzBuilder::new(), Compression::default())
+                .unwrap();
+            encoder.write_all(&test_data).unwrap();
+            // Dropped here without calling `into_inner`: `GzDecoder` below only succeeds if the
+            // CRC32/length trailer got written anyway.
+        }
+        let compressed = writer.0.borrow().clone();
+
+        assert_eq!(decompress_gzip(&compressed), test_data);
+    }
+}
@@ -0,0 +1,123 @@
+//! Knobs controlling how much effort the LZ77 step spends searching for matches, trading
+//! compression ratio for speed.
+
+use std::cmp;
+
+/// A compression effort level, `0` (no compression at all) through `9` (maximum effort),
+/// following zlib's convention. Maps down to a concrete `CompressionOptions` via `to_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Compression(u8);
+
+impl Compression {
+    /// Clamps `level` to the valid `0..=9` range.
+    pub fn new(level: u8) -> Compression {
+        Compression(cmp::min(level, 9))
+    }
+
+    /// No compression: every block is written as a stored block.
+    pub fn none() -> Compression {
+        Compression(0)
+    }
+
+    pub fn fast() -> Compression {
+        Compression(1)
+    }
+
+    pub fn default() -> Compression {
+        Compression(6)
+    }
+
+    pub fn best() -> Compression {
+        Compression(9)
+    }
+
+    /// The raw `0..=9` level.
+    pub fn level(self) -> u8 {
+        self.0
+    }
+
+    /// Whether this level disables compression entirely (`compress_data_dynamic` writes the input
+    /// as `BType::NoCompression` blocks when this is set, regardless of input length).
+    pub fn is_none(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The concrete LZ77 search parameters this level maps to, following the same general shape
+    /// as miniz_oxide's level table: low levels favor speed with a short, bounded hash-chain
+    /// search, high levels walk much longer chains (up to 4096 entries at level 9) for a better
+    /// match.
+    pub fn to_options(self) -> CompressionOptions {
+        match self.0 {
+            0 | 1 => CompressionOptions {
+                max_chain_length: 4,
+                good_match_length: 8,
+                nice_match_length: 16,
+                max_lazy_match: 0,
+            },
+            2 | 3 => CompressionOptions {
+                max_chain_length: 16,
+                good_match_length: 16,
+                nice_match_length: 32,
+                max_lazy_match: 0,
+            },
+            4 | 5 => CompressionOptions {
+                max_chain_length: 32,
+                good_match_length: 16,
+                nice_match_length: 64,
+                max_lazy_match: 32,
+            },
+            6 | 7 | 8 => CompressionOptions {
+                max_chain_length: 128,
+                good_match_length: 32,
+                nice_match_length: 128,
+                max_lazy_match: 128,
+            },
+            _ => CompressionOptions {
+                max_chain_length: 4096,
+                good_match_length: 32,
+                nice_match_length: 258,
+                max_lazy_match: 258,
+            },
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression::default()
+    }
+}
+
+/// Low-level parameters controlling the LZ77 match search. Most users should go through
+/// `Compression`'s presets rather than constructing this directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionOptions {
+    /// How many entries of a hash chain to walk before giving up on finding a longer match.
+    pub max_chain_length: u16,
+    /// Once a match at least this long is found, the remaining chain search budget is halved,
+    /// on the assumption that a long enough match has already been found to not be worth an
+    /// exhaustive search for something marginally better.
+    pub good_match_length: u16,
+    /// A match at least this long is accepted immediately, ending the search.
+    pub nice_match_length: u16,
+    /// When lazy matching (see `lz77::lz77_compress_block`) is enabled, a match already at least
+    /// this long is taken immediately; only a shorter one is deferred to see if the next position
+    /// has something longer. `0` disables lazy matching entirely.
+    pub max_lazy_match: u16,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_level_zero_is_none() {
+        assert!(Compression::none().is_none());
+        assert!(!Compression::best().is_none());
+    }
+
+    #[test]
+    fn test_level_clamped() {
+        assert_eq!(Compression::new(255).level(), 9);
+    }
+}
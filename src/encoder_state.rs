@@ -0,0 +1,93 @@
+//! Tracks the huffman table currently in effect and writes block headers and symbols through it.
+
+use std::io::{self, Write};
+
+use bitstream::{BitWriter, LsbWriter};
+use huffman_table::{HuffmanTable, FIXED_CODE_LENGTHS, FIXED_CODE_LENGTHS_DISTANCE};
+use output_writer::LDPair;
+
+/// Which of the three block encodings described in RFC 1951 section 3.2.3 a block uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BType {
+    NoCompression,
+    FixedHuffman,
+    DynamicHuffman,
+}
+
+/// Bundles the bit-level writer with whichever huffman table the current block is using, so
+/// writing a symbol doesn't require passing the table around separately.
+pub struct EncoderState<W: Write> {
+    pub writer: LsbWriter<W>,
+    huffman_table: HuffmanTable,
+}
+
+impl<W: Write> EncoderState<W> {
+    pub fn new(huffman_table: HuffmanTable, writer: W) -> EncoderState<W> {
+        EncoderState {
+            writer: LsbWriter::new(writer),
+            huffman_table: huffman_table,
+        }
+    }
+
+    /// An `EncoderState` already set up with the fixed huffman codes, for `BType::FixedHuffman`
+    /// blocks.
+    pub fn fixed(writer: W) -> EncoderState<W> {
+        let huffman_table = HuffmanTable::from_lengths(&FIXED_CODE_LENGTHS,
+                                                        &FIXED_CODE_LENGTHS_DISTANCE)
+            .expect("Failed to build fixed huffman table, this should not happen!");
+        EncoderState {
+            writer: LsbWriter::new(writer),
+            huffman_table: huffman_table,
+        }
+    }
+
+    /// Write the 3-bit block header (BFINAL, BTYPE) that precedes every block.
+    pub fn write_start_of_block(&mut self, fixed: bool, final_block: bool) -> io::Result<()> {
+        try!(self.writer.write_bits(final_block as u16, 1));
+        let btype = if fixed { 0b01 } else { 0b10 };
+        self.writer.write_bits(btype, 2)
+    }
+
+    pub fn write_end_of_block(&mut self) -> io::Result<()> {
+        let (code, length) = self.huffman_table.get_end_of_block();
+        self.writer.write_bits(code, length)
+    }
+
+    /// Write a single LZ77 symbol using the currently active huffman table.
+    pub fn write_ldpair(&mut self, ld: LDPair) -> io::Result<()> {
+        match ld {
+            LDPair::Literal(value) => {
+                let (code, length) = self.huffman_table.get_literal(value);
+                self.writer.write_bits(code, length)
+            }
+            LDPair::LengthDistance { length, distance } => {
+                let (l_code, l_length, l_extra, l_extra_bits) =
+                    self.huffman_table.get_length(length);
+                try!(self.writer.write_bits(l_code, l_length));
+                if l_extra_bits > 0 {
+                    try!(self.writer.write_bits(l_extra, l_extra_bits));
+                }
+
+                let (d_code, d_length, d_extra, d_extra_bits) =
+                    self.huffman_table.get_distance(distance);
+                try!(self.writer.write_bits(d_code, d_length));
+                if d_extra_bits > 0 {
+                    try!(self.writer.write_bits(d_extra, d_extra_bits));
+                }
+                Ok(())
+            }
+            LDPair::EndOfBlock => self.write_end_of_block(),
+        }
+    }
+
+    /// Swap in the huffman table generated for the block currently being written, from a set of
+    /// literal/length and distance code lengths.
+    pub fn update_huffman_table(&mut self, l_lengths: &[u8], d_lengths: &[u8]) -> io::Result<()> {
+        self.huffman_table = try!(HuffmanTable::from_lengths(l_lengths, d_lengths));
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
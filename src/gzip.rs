@@ -0,0 +1,126 @@
+//! Support for wrapping a raw DEFLATE stream in the gzip container format (RFC 1952).
+//!
+//! Lives behind the `gzip` cargo feature since most users only need the bare DEFLATE stream or
+//! the zlib wrapper, and gzip pulls in a CRC32 table on top of that.
+
+use std::io::{self, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use compression_options::Compression;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const CM_DEFLATE: u8 = 8;
+
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+
+/// OS byte meaning "unknown", since this crate doesn't try to detect the host OS.
+const OS_UNKNOWN: u8 = 255;
+
+/// Optional fields that can be set on a gzip stream before compression starts: the original file
+/// name, a free-form comment, and the modification time.
+#[derive(Debug, Clone, Default)]
+pub struct GzBuilder {
+    filename: Option<Vec<u8>>,
+    comment: Option<Vec<u8>>,
+    mtime: u32,
+}
+
+impl GzBuilder {
+    pub fn new() -> GzBuilder {
+        GzBuilder::default()
+    }
+
+    /// Set the original file name (the `FNAME` field). Shouldn't contain a zero byte.
+    pub fn filename<T: Into<Vec<u8>>>(mut self, filename: T) -> GzBuilder {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    /// Set a free-form comment (the `FCOMMENT` field). Shouldn't contain a zero byte.
+    pub fn comment<T: Into<Vec<u8>>>(mut self, comment: T) -> GzBuilder {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Set the modification time as a Unix timestamp. Defaults to `0` (unknown), matching gzip's
+    /// `-n`/`--no-name` behavior for the timestamp.
+    pub fn mtime(mut self, mtime: u32) -> GzBuilder {
+        self.mtime = mtime;
+        self
+    }
+
+    /// Write the 10-byte gzip header, followed by the optional `FNAME`/`FCOMMENT` fields.
+    pub fn write_header<W: Write>(&self, writer: &mut W, level: Compression) -> io::Result<()> {
+        let mut flg = 0u8;
+        if self.filename.is_some() {
+            flg |= FNAME;
+        }
+        if self.comment.is_some() {
+            flg |= FCOMMENT;
+        }
+
+        try!(writer.write_all(&GZIP_MAGIC));
+        try!(writer.write_u8(CM_DEFLATE));
+        try!(writer.write_u8(flg));
+        try!(writer.write_u32::<LittleEndian>(self.mtime));
+        try!(writer.write_u8(xfl(level)));
+        try!(writer.write_u8(OS_UNKNOWN));
+
+        if let Some(ref filename) = self.filename {
+            try!(writer.write_all(filename));
+            try!(writer.write_u8(0));
+        }
+        if let Some(ref comment) = self.comment {
+            try!(writer.write_all(comment));
+            try!(writer.write_u8(0));
+        }
+
+        Ok(())
+    }
+}
+
+/// The extra-flags byte: a hint about how much effort went into compression.
+fn xfl(level: Compression) -> u8 {
+    if level.level() >= 9 {
+        2
+    } else if level.level() <= 1 {
+        4
+    } else {
+        0
+    }
+}
+
+/// Write the 8-byte gzip trailer: the CRC32 of the uncompressed data, then its length modulo
+/// 2^32, both little-endian.
+pub fn write_trailer<W: Write>(writer: &mut W, crc: u32, uncompressed_length: u64) -> io::Result<()> {
+    try!(writer.write_u32::<LittleEndian>(crc));
+    writer.write_u32::<LittleEndian>(uncompressed_length as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_header_starts_with_magic() {
+        let mut out = Vec::new();
+        GzBuilder::new().write_header(&mut out, Compression::default()).unwrap();
+        assert_eq!(&out[0..2], &GZIP_MAGIC);
+        assert_eq!(out[2], CM_DEFLATE);
+        assert_eq!(out.len(), 10);
+    }
+
+    #[test]
+    fn test_header_with_filename() {
+        let mut out = Vec::new();
+        GzBuilder::new()
+            .filename(&b"a.txt"[..])
+            .write_header(&mut out, Compression::default())
+            .unwrap();
+        assert_eq!(out[3] & FNAME, FNAME);
+        assert_eq!(&out[10..15], b"a.txt");
+        assert_eq!(out[15], 0);
+    }
+}
@@ -0,0 +1,158 @@
+//! Writing of the dynamic huffman code length header described in RFC 1951 section 3.2.7.
+
+use std::cmp;
+use std::io;
+
+use bitstream::BitWriter;
+use huffman_table::codes_from_lengths;
+use length_encode::huffman_lengths_from_frequency;
+
+/// The deflate spec requires at least this many literal/length code lengths to be transmitted,
+/// even if most of them end up being `0` (unused).
+pub const MIN_NUM_LITERALS_AND_LENGTHS: usize = 257;
+/// The deflate spec requires at least this many distance code lengths to be transmitted.
+pub const MIN_NUM_DISTANCES: usize = 1;
+
+/// The order the code-length code lengths are transmitted in, chosen so that the commonly-unused
+/// ones end up at the end and can be omitted via `HCLEN`.
+const HCLEN_ORDER: [usize; 19] =
+    [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Repeat the previous code length 3-6 times.
+const REP_PREVIOUS: usize = 16;
+/// Repeat a code length of zero 3-10 times.
+const REP_ZERO_SHORT: usize = 17;
+/// Repeat a code length of zero 11-138 times.
+const REP_ZERO_LONG: usize = 18;
+
+/// Trim trailing entries with a frequency of `0` off of `frequencies`, while keeping at least
+/// `min_length` entries, since the deflate format requires a minimum number of lengths to be
+/// transmitted regardless of how many are actually used.
+pub fn remove_trailing_zeroes(frequencies: &[u32], min_length: usize) -> &[u32] {
+    let last_used = frequencies.iter().rposition(|&f| f != 0).map_or(0, |i| i + 1);
+    &frequencies[..cmp::max(last_used, min_length)]
+}
+
+enum LengthToken {
+    Length(u8),
+    RepeatPrevious(u8),
+    RepeatZero { long: bool, count: u8 },
+}
+
+/// Run-length encode a sequence of code lengths using the DEFLATE code-length alphabet.
+fn rle_encode(lengths: &[u8]) -> Vec<LengthToken> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let value = lengths[i];
+        let mut run = 1;
+        while i + run < lengths.len() && lengths[i + run] == value {
+            run += 1;
+        }
+
+        if value == 0 {
+            let mut remaining = run;
+            while remaining >= 3 {
+                if remaining >= 11 {
+                    let count = cmp::min(remaining, 138);
+                    tokens.push(LengthToken::RepeatZero { long: true, count: count as u8 });
+                    remaining -= count;
+                } else {
+                    let count = cmp::min(remaining, 10);
+                    tokens.push(LengthToken::RepeatZero { long: false, count: count as u8 });
+                    remaining -= count;
+                }
+            }
+            for _ in 0..remaining {
+                tokens.push(LengthToken::Length(0));
+            }
+        } else {
+            tokens.push(LengthToken::Length(value));
+            let mut remaining = run - 1;
+            while remaining >= 3 {
+                let count = cmp::min(remaining, 6);
+                tokens.push(LengthToken::RepeatPrevious(count as u8));
+                remaining -= count;
+            }
+            for _ in 0..remaining {
+                tokens.push(LengthToken::Length(value));
+            }
+        }
+        i += run;
+    }
+    tokens
+}
+
+/// Write the literal/length and distance huffman code lengths to the bitstream, as required at
+/// the start of a dynamic huffman block.
+pub fn write_huffman_lengths<W: BitWriter>(l_lengths: &[u8],
+                                           d_lengths: &[u8],
+                                           writer: &mut W)
+                                           -> io::Result<()> {
+    let mut all_lengths = Vec::with_capacity(l_lengths.len() + d_lengths.len());
+    all_lengths.extend_from_slice(l_lengths);
+    all_lengths.extend_from_slice(d_lengths);
+
+    let tokens = rle_encode(&all_lengths);
+
+    let mut cl_frequencies = [0u32; 19];
+    for token in &tokens {
+        let symbol = match *token {
+            LengthToken::Length(l) => l as usize,
+            LengthToken::RepeatPrevious(_) => REP_PREVIOUS,
+            LengthToken::RepeatZero { long: false, .. } => REP_ZERO_SHORT,
+            LengthToken::RepeatZero { long: true, .. } => REP_ZERO_LONG,
+        };
+        cl_frequencies[symbol] += 1;
+    }
+
+    let cl_lengths = huffman_lengths_from_frequency(&cl_frequencies, 7);
+    let cl_codes = codes_from_lengths(&cl_lengths);
+
+    let hclen = cmp::max(4,
+                          HCLEN_ORDER.iter()
+                              .rposition(|&i| cl_lengths[i] != 0)
+                              .map_or(4, |i| i + 1));
+
+    try!(writer.write_bits((l_lengths.len() - MIN_NUM_LITERALS_AND_LENGTHS) as u16, 5));
+    try!(writer.write_bits((d_lengths.len() - MIN_NUM_DISTANCES) as u16, 5));
+    try!(writer.write_bits((hclen - 4) as u16, 4));
+
+    for &index in HCLEN_ORDER.iter().take(hclen) {
+        try!(writer.write_bits(cl_lengths[index] as u16, 3));
+    }
+
+    for token in &tokens {
+        match *token {
+            LengthToken::Length(l) => {
+                try!(writer.write_bits(cl_codes[l as usize], cl_lengths[l as usize]));
+            }
+            LengthToken::RepeatPrevious(count) => {
+                try!(writer.write_bits(cl_codes[REP_PREVIOUS], cl_lengths[REP_PREVIOUS]));
+                try!(writer.write_bits((count - 3) as u16, 2));
+            }
+            LengthToken::RepeatZero { long: false, count } => {
+                try!(writer.write_bits(cl_codes[REP_ZERO_SHORT], cl_lengths[REP_ZERO_SHORT]));
+                try!(writer.write_bits((count - 3) as u16, 3));
+            }
+            LengthToken::RepeatZero { long: true, count } => {
+                try!(writer.write_bits(cl_codes[REP_ZERO_LONG], cl_lengths[REP_ZERO_LONG]));
+                try!(writer.write_bits((count - 11) as u16, 7));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_remove_trailing_zeroes() {
+        let freqs = [1, 0, 3, 0, 0, 0];
+        assert_eq!(remove_trailing_zeroes(&freqs, 1), &[1, 0, 3]);
+        assert_eq!(remove_trailing_zeroes(&freqs, 5), &[1, 0, 3, 0, 0]);
+    }
+}
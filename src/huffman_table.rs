@@ -0,0 +1,170 @@
+//! Construction and lookup of the huffman codes used to encode literals, lengths and distances.
+
+use std::io;
+
+/// The maximum bit length of a single huffman code allowed by the DEFLATE format.
+pub const MAX_CODE_LENGTH: u8 = 15;
+
+/// Number of used literal/length codes (0-255 literals, 256 end-of-block, 257-285 lengths).
+pub const NUM_LITERALS_AND_LENGTHS: usize = 286;
+/// Number of used distance codes.
+pub const NUM_DISTANCE_CODES: usize = 30;
+
+/// Lower bound of the match length represented by each length code, indexed from code `257`.
+pub static LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+
+/// Number of extra bits following each length code.
+pub static LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Lower bound of the distance represented by each distance code.
+pub static DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+/// Number of extra bits following each distance code.
+pub static DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+/// The bit lengths used for literals/lengths in `BType::FixedHuffman` blocks, as fixed by the
+/// DEFLATE specification.
+pub static FIXED_CODE_LENGTHS: [u8; 288] = [
+    8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+    8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+    8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+    8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+    8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+    8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+    9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9,
+    9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9,
+    9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9,
+    9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9,
+    9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 8, 8, 8, 8, 8, 8, 8, 8,
+];
+
+/// The bit lengths used for distances in `BType::FixedHuffman` blocks: all 5 bits.
+pub static FIXED_CODE_LENGTHS_DISTANCE: [u8; 30] = [5; 30];
+
+/// Get the length code and number of extra bits for a match of the given length (3-258).
+pub fn length_code(length: u16) -> (u16, u8, u16) {
+    let index = LENGTH_BASE.iter()
+        .rposition(|&base| base <= length)
+        .expect("Match length out of range");
+    let extra = length - LENGTH_BASE[index];
+    (257 + index as u16, LENGTH_EXTRA_BITS[index], extra)
+}
+
+/// Get the distance code and number of extra bits for the given match distance (1-32768).
+pub fn distance_code(distance: u16) -> (u16, u8, u16) {
+    let index = DISTANCE_BASE.iter()
+        .rposition(|&base| base <= distance)
+        .expect("Match distance out of range");
+    let extra = distance - DISTANCE_BASE[index];
+    (index as u16, DISTANCE_EXTRA_BITS[index], extra)
+}
+
+/// A table of huffman codes (and their bit lengths) for literals/lengths and distances, used to
+/// encode symbols produced by the LZ77 step into the output bitstream.
+pub struct HuffmanTable {
+    codes: Vec<u16>,
+    lengths: Vec<u8>,
+    distance_codes: Vec<u16>,
+    distance_lengths: Vec<u8>,
+}
+
+impl HuffmanTable {
+    /// An empty table, used as a placeholder before the first block's codes have been chosen.
+    pub fn empty() -> HuffmanTable {
+        HuffmanTable {
+            codes: Vec::new(),
+            lengths: Vec::new(),
+            distance_codes: Vec::new(),
+            distance_lengths: Vec::new(),
+        }
+    }
+
+    /// Build a table from literal/length and distance code lengths, as produced by
+    /// `length_encode::huffman_lengths_from_frequency` or the fixed tables above.
+    pub fn from_lengths(l_lengths: &[u8], d_lengths: &[u8]) -> io::Result<HuffmanTable> {
+        Ok(HuffmanTable {
+            codes: codes_from_lengths(l_lengths),
+            lengths: l_lengths.to_vec(),
+            distance_codes: codes_from_lengths(d_lengths),
+            distance_lengths: d_lengths.to_vec(),
+        })
+    }
+
+    pub fn get_literal(&self, value: u8) -> (u16, u8) {
+        (self.codes[value as usize], self.lengths[value as usize])
+    }
+
+    pub fn get_end_of_block(&self) -> (u16, u8) {
+        (self.codes[256], self.lengths[256])
+    }
+
+    pub fn get_length(&self, length: u16) -> (u16, u8, u16, u8) {
+        let (code, extra_bits, extra) = length_code(length);
+        (self.codes[code as usize], self.lengths[code as usize], extra, extra_bits)
+    }
+
+    pub fn get_distance(&self, distance: u16) -> (u16, u8, u16, u8) {
+        let (code, extra_bits, extra) = distance_code(distance);
+        (self.distance_codes[code as usize], self.distance_lengths[code as usize], extra, extra_bits)
+    }
+}
+
+/// Canonical huffman code assignment (RFC 1951 section 3.2.2) from a table of code lengths.
+pub(crate) fn codes_from_lengths(lengths: &[u8]) -> Vec<u16> {
+    use bit_reverse::reverse_bits;
+
+    let max_length = lengths.iter().cloned().max().unwrap_or(0);
+    let mut bl_count = vec![0u16; max_length as usize + 1];
+    for &l in lengths {
+        if l > 0 {
+            bl_count[l as usize] += 1;
+        }
+    }
+
+    let mut code = 0u16;
+    let mut next_code = vec![0u16; max_length as usize + 1];
+    for bits in 1..max_length as usize + 1 {
+        code = (code + bl_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+
+    let mut codes = vec![0u16; lengths.len()];
+    for (i, &length) in lengths.iter().enumerate() {
+        if length > 0 {
+            codes[i] = reverse_bits(next_code[length as usize], length);
+            next_code[length as usize] += 1;
+        }
+    }
+    codes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_length_code() {
+        assert_eq!(length_code(3), (257, 0, 0));
+        assert_eq!(length_code(258), (285, 0, 0));
+        assert_eq!(length_code(10), (264, 0, 0));
+    }
+
+    #[test]
+    fn test_distance_code() {
+        assert_eq!(distance_code(1), (0, 0, 0));
+        assert_eq!(distance_code(24577), (29, 13, 0));
+    }
+}
@@ -0,0 +1,125 @@
+//! Generation of huffman code lengths from symbol frequencies.
+
+use std::cmp;
+
+/// A node in the huffman tree being built. Leaves carry a symbol index, internal nodes link to
+/// their two children.
+enum Node {
+    Leaf { freq: u64, symbol: usize },
+    Internal { freq: u64, left: Box<Node>, right: Box<Node> },
+}
+
+impl Node {
+    fn freq(&self) -> u64 {
+        match *self {
+            Node::Leaf { freq, .. } => freq,
+            Node::Internal { freq, .. } => freq,
+        }
+    }
+}
+
+fn record_depths(node: &Node, depth: u8, lengths: &mut [u8]) {
+    match *node {
+        Node::Leaf { symbol, .. } => lengths[symbol] = cmp::max(depth, 1),
+        Node::Internal { ref left, ref right, .. } => {
+            record_depths(left, depth + 1, lengths);
+            record_depths(right, depth + 1, lengths);
+        }
+    }
+}
+
+/// Build a canonical set of huffman code lengths, one per symbol in `frequencies`, such that no
+/// code is longer than `max_length` bits.
+///
+/// Symbols with a frequency of `0` are assigned a length of `0` (i.e. they aren't given a code at
+/// all, since they never occur).
+pub fn huffman_lengths_from_frequency(frequencies: &[u32], max_length: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+
+    let mut nodes: Vec<Node> = frequencies.iter()
+        .enumerate()
+        .filter(|&(_, &freq)| freq > 0)
+        .map(|(symbol, &freq)| Node::Leaf { freq: freq as u64, symbol: symbol })
+        .collect();
+
+    match nodes.len() {
+        0 => return lengths,
+        1 => {
+            if let Node::Leaf { symbol, .. } = nodes[0] {
+                lengths[symbol] = 1;
+            }
+            return lengths;
+        }
+        _ => {}
+    }
+
+    while nodes.len() > 1 {
+        nodes.sort_by(|a, b| b.freq().cmp(&a.freq()));
+        let left = nodes.pop().expect("at least two nodes");
+        let right = nodes.pop().expect("at least two nodes");
+        nodes.push(Node::Internal {
+            freq: left.freq() + right.freq(),
+            left: Box::new(left),
+            right: Box::new(right),
+        });
+    }
+
+    record_depths(&nodes[0], 0, &mut lengths);
+    limit_code_lengths(&mut lengths, max_length);
+    lengths
+}
+
+/// Reduce overlong code lengths to fit within `max_length`, then repair the Kraft inequality
+/// (which an unbalanced set of frequencies can otherwise violate once lengths are clamped) by
+/// lengthening the shortest codes until the set is valid again.
+fn limit_code_lengths(lengths: &mut [u8], max_length: u8) {
+    if lengths.iter().all(|&l| l <= max_length) {
+        return;
+    }
+
+    for length in lengths.iter_mut() {
+        if *length > max_length {
+            *length = max_length;
+        }
+    }
+
+    let full = 1u64 << max_length;
+    loop {
+        let kraft_sum: u64 = lengths.iter()
+            .filter(|&&l| l > 0)
+            .map(|&l| 1u64 << (max_length - l))
+            .sum();
+        if kraft_sum <= full {
+            break;
+        }
+        let shortest = lengths.iter()
+            .enumerate()
+            .filter(|&(_, &l)| l > 0 && l < max_length)
+            .min_by_key(|&(_, &l)| l)
+            .map(|(i, _)| i);
+        match shortest {
+            Some(i) => lengths[i] += 1,
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_single_symbol() {
+        let freqs = [0, 5, 0];
+        let lengths = huffman_lengths_from_frequency(&freqs, 15);
+        assert_eq!(lengths, vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn test_no_overlong_codes() {
+        let mut freqs = vec![1u32; 20];
+        freqs[0] = 1000;
+        let lengths = huffman_lengths_from_frequency(&freqs, 7);
+        assert!(lengths.iter().all(|&l| l <= 7));
+    }
+}
@@ -1,8 +1,10 @@
 //! An implementation an encoder using [DEFLATE](http://www.gzip.org/zlib/rfc-deflate.html) \
 //! compression algorightm in pure rust.
 //!
-//! This library provides functions to compress data (currently only in-memory) using DEFLATE,
-//! both with and without a [zlib](https://tools.ietf.org/html/rfc1950) header/trailer
+//! This library provides functions to compress data using DEFLATE, both with and without a
+//! [zlib](https://tools.ietf.org/html/rfc1950) header/trailer, as well as `DeflateEncoder` and
+//! `ZlibEncoder`, which implement `std::io::Write` for incremental compression of data that
+//! doesn't fit in memory all at once, or that arrives over time.
 //! The current implementation is still pretty slow compared to C-libraries like zlib and miniz,
 //! particularly for large files, and is therefore not recommended for production use.
 
@@ -26,13 +28,25 @@ mod checksum;
 mod bit_reverse;
 mod bitstream;
 mod encoder_state;
+mod compression_options;
+mod compress;
+#[cfg(feature = "gzip")]
+mod gzip;
+
+pub use compress::{DeflateEncoder, Flush, ZlibEncoder};
+pub use zlib::CompressionLevel;
+pub use compression_options::{Compression, CompressionOptions};
+#[cfg(feature = "gzip")]
+pub use gzip::GzBuilder;
+#[cfg(feature = "gzip")]
+pub use compress::GzEncoder;
 
 use byteorder::BigEndian;
 
 use huffman_table::*;
 #[cfg(test)]
 use lz77::LDPair;
-use lz77::create_buffer;
+use lz77::create_buffer_with_dictionary;
 use huffman_lengths::{write_huffman_lengths, remove_trailing_zeroes, MIN_NUM_LITERALS_AND_LENGTHS,
                       MIN_NUM_DISTANCES};
 use length_encode::huffman_lengths_from_frequency;
@@ -42,13 +56,28 @@ use std::io;
 use encoder_state::{EncoderState, BType};
 use stored_block::compress_block_stored;
 
-/// Determine if the block is long enough for it to be worth using dynamic huffman codes or just
-/// Write the data directly
-fn block_type_for_length(length: usize) -> BType {
-    // TODO: Do proper testing to determine what values make sense here
-    if length < 20 {
+/// Overhead, in bits, of a stored block's framing: the 3-bit BFINAL/BTYPE header, padding out to
+/// the next byte boundary (up to 7 bits), and the 4-byte length/one's-complement-length pair.
+const STORED_BLOCK_OVERHEAD_BITS: u64 = 3 + 7 + 4 * 8;
+/// The 3-bit BFINAL/BTYPE header common to fixed and dynamic huffman blocks, counted separately
+/// from `output_writer::DynamicWriter`'s per-type cost estimates.
+const BLOCK_HEADER_BITS: u64 = 3;
+
+/// Pick the cheapest `BType` for a block of `length` bytes, given the LZ77 symbols already
+/// gathered for it in `lz77_writer`: compares the estimated encoded size of dynamic and fixed
+/// huffman coding (see `output_writer::DynamicWriter::estimated_dynamic_cost_bits`/
+/// `estimated_fixed_cost_bits`) against simply storing the bytes verbatim, rather than guessing
+/// from the total input length. Also used by `compress::DeflateEncoder`, so that the streaming
+/// and one-shot encoders pick the same `BType` for the same data.
+pub(crate) fn block_type_for_block(length: usize, lz77_writer: &output_writer::DynamicWriter) -> BType {
+    let dynamic_bits = lz77_writer.estimated_dynamic_cost_bits() + BLOCK_HEADER_BITS;
+    let fixed_bits = lz77_writer.estimated_fixed_cost_bits() + BLOCK_HEADER_BITS;
+    let stored_bits = length as u64 * 8 + STORED_BLOCK_OVERHEAD_BITS;
+
+    if length <= stored_block::MAX_STORED_BLOCK_LENGTH && stored_bits <= fixed_bits &&
+       stored_bits <= dynamic_bits {
         BType::NoCompression
-    } else if length < 70 {
+    } else if fixed_bits <= dynamic_bits {
         BType::FixedHuffman
     } else {
         BType::DynamicHuffman
@@ -84,99 +113,111 @@ fn compress_data_fixed(input: &[u8]) -> Vec<u8> {
 }
 
 fn compress_data_dynamic<RC: RollingChecksum, W: Write>(input: &[u8],
+                                                        dictionary: &[u8],
                                                         mut writer: &mut W,
-                                                        mut checksum: &mut RC)
+                                                        mut checksum: &mut RC,
+                                                        level: Compression)
                                                         -> io::Result<()> {
     let mut state = EncoderState::new(huffman_table::HuffmanTable::empty(), &mut writer);
+    checksum.update_from_slice(input);
+
+    // `level.is_none()` forces every block to be stored regardless of length, and the LZ77 step
+    // never runs for empty input (its very first call would already be the last block), so empty
+    // input needs the same treatment to still produce a valid (empty) stream. A stored block's
+    // length is a 16-bit field, so `input` has to be split into `MAX_STORED_BLOCK_LENGTH`-sized
+    // chunks rather than written out as a single block.
+    if level.is_none() || input.is_empty() {
+        use bitstream::BitWriter;
+        let chunks: Vec<&[u8]> = if input.is_empty() {
+            vec![input]
+        } else {
+            input.chunks(stored_block::MAX_STORED_BLOCK_LENGTH).collect()
+        };
+        let last = chunks.len() - 1;
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let header = if i == last {
+                stored_block::STORED_FIRST_BYTE_FINAL
+            } else {
+                stored_block::STORED_FIRST_BYTE
+            };
+            try!(state.writer.write_bits(header.into(), 3));
+            try!(state.flush());
+            try!(compress_block_stored(chunk, &mut state.writer));
+        }
+        return state.flush();
+    }
 
-    let block_type = block_type_for_length(input.len());
-
-    match block_type {
-        BType::DynamicHuffman | BType::FixedHuffman => {
-            let mut lz77_state = lz77::LZ77State::new(input);
-            let mut lz77_writer = output_writer::DynamicWriter::new();
-            let mut buffer = create_buffer(input);
-
-            checksum.update_from_slice(input);
-
-            match block_type {
-                BType::DynamicHuffman => {
-                    while !lz77_state.is_last_block() {
-                        lz77::lz77_compress_block::<output_writer::DynamicWriter,
-                                                    RC>(input,
-                                                        &mut lz77_state,
-                                                        &mut buffer,
-                                                        &mut lz77_writer,
-                                                        &mut checksum);
-                        try!(state.write_start_of_block(false, lz77_state.is_last_block()));
-
-                        let (l_lengths, d_lengths) = {
-                            let (l_freqs, d_freqs) = lz77_writer.get_frequencies();
-                            // The huffman spec allows us to exclude zeroes at the end of the table
-                            // of huffman lengths. Since a frequency of 0 will give an huffman
-                            // length of 0. We strip off the trailing zeroes before even generating
-                            // the lengths to save some work.
-                            // There is however a minimum number of values we have to keep according
-                            // to the deflate spec.
-                            (
-                                huffman_lengths_from_frequency(
-                                    remove_trailing_zeroes(l_freqs, MIN_NUM_LITERALS_AND_LENGTHS),
-                                    MAX_CODE_LENGTH
-                            ),
-                                huffman_lengths_from_frequency(
-                                    remove_trailing_zeroes(d_freqs, MIN_NUM_DISTANCES),
-                                    MAX_CODE_LENGTH)
-                            )
-                        };
-                        try!(write_huffman_lengths(&l_lengths, &d_lengths, &mut state.writer));
-
-                        state.update_huffman_table(&l_lengths, &d_lengths)
-                            .expect("Fatal error!: Failed to create huffman table!");
-
-                        for &ld in lz77_writer.get_buffer() {
-                            try!(state.write_ldpair(ld));
-                        }
-
-                        // End of block is written in write_ldpair.
-                        lz77_writer.clear();
-                    }
-                }
-                BType::FixedHuffman => {
-
-                    lz77::lz77_compress_block::<output_writer::DynamicWriter, RC>(input,
-                                                                                  &mut lz77_state,
-                                                                                  &mut buffer,
-                                                                                  &mut lz77_writer,
-                                                                                  &mut checksum);
-                    state.update_huffman_table(&huffman_table::FIXED_CODE_LENGTHS,
-                                              &huffman_table::FIXED_CODE_LENGTHS_DISTANCE)
-                        .unwrap();
-                    try!(state.write_start_of_block(true, true));
-                    for &ld in lz77_writer.get_buffer() {
-                        try!(state.write_ldpair(ld));
-                    }
-                    lz77_writer.clear();
+    let mut lz77_state = lz77::LZ77State::with_dictionary(input, dictionary, level.to_options());
+    let mut lz77_writer = output_writer::DynamicWriter::new();
+    let buffer = create_buffer_with_dictionary(input, dictionary);
+
+    while !lz77_state.is_last_block() {
+        let position_before = lz77_state.position();
+        lz77::lz77_compress_block::<output_writer::DynamicWriter, RC>(&buffer,
+                                                                       &mut lz77_state,
+                                                                       &mut lz77_writer,
+                                                                       &mut checksum);
+        let block_length = lz77_state.position() - position_before;
+        let final_block = lz77_state.is_last_block();
+
+        match block_type_for_block(block_length, &lz77_writer) {
+            BType::NoCompression => {
+                use bitstream::BitWriter;
+                let header = if final_block {
+                    stored_block::STORED_FIRST_BYTE_FINAL
+                } else {
+                    stored_block::STORED_FIRST_BYTE
+                };
+                try!(state.writer.write_bits(header.into(), 3));
+                try!(state.flush());
+                let block_data = &input[position_before..position_before + block_length];
+                try!(compress_block_stored(block_data, &mut state.writer));
+            }
+            BType::FixedHuffman => {
+                state.update_huffman_table(&huffman_table::FIXED_CODE_LENGTHS,
+                                          &huffman_table::FIXED_CODE_LENGTHS_DISTANCE)
+                    .unwrap();
+                try!(state.write_start_of_block(true, final_block));
+                for &ld in lz77_writer.get_buffer() {
+                    try!(state.write_ldpair(ld));
                 }
-                BType::NoCompression => {
-                    unreachable!();
+            }
+            BType::DynamicHuffman => {
+                try!(state.write_start_of_block(false, final_block));
+
+                let (l_lengths, d_lengths) = {
+                    let (l_freqs, d_freqs) = lz77_writer.get_frequencies();
+                    // The huffman spec allows us to exclude zeroes at the end of the table of
+                    // huffman lengths. Since a frequency of 0 will give an huffman length of 0,
+                    // we strip off the trailing zeroes before even generating the lengths to save
+                    // some work. There is however a minimum number of values we have to keep
+                    // according to the deflate spec.
+                    (
+                        huffman_lengths_from_frequency(
+                            remove_trailing_zeroes(l_freqs, MIN_NUM_LITERALS_AND_LENGTHS),
+                            MAX_CODE_LENGTH
+                    ),
+                        huffman_lengths_from_frequency(
+                            remove_trailing_zeroes(d_freqs, MIN_NUM_DISTANCES),
+                            MAX_CODE_LENGTH)
+                    )
+                };
+                try!(write_huffman_lengths(&l_lengths, &d_lengths, &mut state.writer));
+
+                state.update_huffman_table(&l_lengths, &d_lengths)
+                    .expect("Fatal error!: Failed to create huffman table!");
+
+                for &ld in lz77_writer.get_buffer() {
+                    try!(state.write_ldpair(ld));
                 }
+                // End of block is written in write_ldpair.
             }
-
-        }
-        BType::NoCompression => {
-            use bitstream::BitWriter;
-            state.writer.write_bits(stored_block::STORED_FIRST_BYTE_FINAL.into(), 3).unwrap();
-            state.flush().unwrap();
-            compress_block_stored(input, &mut state.writer).unwrap();
-            // Update the checksum.
-            // We've already added the two first bytes to the checksum earlier.
-            checksum.update_from_slice(input);
         }
-    }
 
-    state.flush().unwrap();
+        lz77_writer.clear();
+    }
 
-    Ok(())
+    state.flush()
 }
 
 /// Compress the given slice of bytes with DEFLATE compression.
@@ -192,8 +233,22 @@ fn compress_data_dynamic<RC: RollingChecksum, W: Write>(input: &[u8],
 /// # let _ = compressed_data;
 /// ```
 pub fn deflate_bytes(input: &[u8]) -> Vec<u8> {
+    deflate_bytes_conf(input, Compression::default())
+}
+
+/// As `deflate_bytes`, but with the compression level/effort set explicitly.
+///
+/// # Examples
+///
+/// ```
+/// use deflate::{deflate_bytes_conf, Compression};
+/// let data = b"This is some test data";
+/// let compressed_data = deflate_bytes_conf(data, Compression::best());
+/// # let _ = compressed_data;
+/// ```
+pub fn deflate_bytes_conf(input: &[u8], level: Compression) -> Vec<u8> {
     let mut writer = Cursor::new(Vec::with_capacity(input.len() / 3));
-    compress_data_dynamic(input, &mut writer, &mut checksum::NoChecksum::new())
+    compress_data_dynamic(input, &[], &mut writer, &mut checksum::NoChecksum::new(), level)
         .expect("Write error!");
     writer.into_inner()
 }
@@ -202,8 +257,6 @@ pub fn deflate_bytes(input: &[u8]) -> Vec<u8> {
 ///
 /// Returns a Vec<u8> of the compressed data.
 ///
-/// Zlib dictionaries are not yet suppored.
-///
 /// # Examples
 ///
 /// ```
@@ -213,14 +266,28 @@ pub fn deflate_bytes(input: &[u8]) -> Vec<u8> {
 /// # let _ = compressed_data;
 /// ```
 pub fn deflate_bytes_zlib(input: &[u8]) -> Vec<u8> {
+    deflate_bytes_zlib_conf(input, Compression::default())
+}
+
+/// As `deflate_bytes_zlib`, but with the compression level/effort set explicitly.
+///
+/// # Examples
+///
+/// ```
+/// use deflate::{deflate_bytes_zlib_conf, Compression};
+/// let data = b"This is some test data";
+/// let compressed_data = deflate_bytes_zlib_conf(data, Compression::best());
+/// # let _ = compressed_data;
+/// ```
+pub fn deflate_bytes_zlib_conf(input: &[u8], level: Compression) -> Vec<u8> {
     use byteorder::WriteBytesExt;
     let mut writer = Cursor::new(Vec::with_capacity(input.len() / 3));
     // Write header
-    zlib::write_zlib_header(&mut writer, zlib::CompressionLevel::Default)
+    zlib::write_zlib_header(&mut writer, zlib::CompressionLevel::from_compression(level))
         .expect("Write error when writing zlib header!");
 
     let mut checksum = checksum::Adler32Checksum::new();
-    compress_data_dynamic(input, &mut writer, &mut checksum)
+    compress_data_dynamic(input, &[], &mut writer, &mut checksum, level)
         .expect("Write error when writing compressed data!");
 
     let hash = checksum.current_hash();
@@ -229,6 +296,90 @@ pub fn deflate_bytes_zlib(input: &[u8]) -> Vec<u8> {
     writer.into_inner()
 }
 
+/// Compress the given slice of bytes with DEFLATE compression, including a zlib header and
+/// trailer, seeding the LZ77 match search with a preset dictionary so the very start of `input`
+/// can already reference data the decoder is expected to already have.
+///
+/// The dictionary isn't written to the output; a decoder needs to be given the same bytes (up to
+/// the last 32768 of them, since that's all the window could ever reach back into) before
+/// decompressing.
+///
+/// # Examples
+///
+/// ```
+/// use deflate::deflate_bytes_zlib_dict;
+/// let data = b"This is some test data";
+/// let compressed_data = deflate_bytes_zlib_dict(data, b"This is some test dictionary");
+/// # let _ = compressed_data;
+/// ```
+pub fn deflate_bytes_zlib_dict(input: &[u8], dictionary: &[u8]) -> Vec<u8> {
+    deflate_bytes_zlib_dict_conf(input, dictionary, Compression::default())
+}
+
+/// As `deflate_bytes_zlib_dict`, but with the compression level/effort set explicitly.
+pub fn deflate_bytes_zlib_dict_conf(input: &[u8], dictionary: &[u8], level: Compression) -> Vec<u8> {
+    use byteorder::WriteBytesExt;
+    let mut writer = Cursor::new(Vec::with_capacity(input.len() / 3));
+
+    let mut dictionary_checksum = checksum::Adler32Checksum::new();
+    dictionary_checksum.update_from_slice(dictionary);
+    zlib::write_zlib_header_with_dictionary(&mut writer,
+                                             zlib::CompressionLevel::from_compression(level),
+                                             dictionary_checksum.current_hash())
+        .expect("Write error when writing zlib header!");
+
+    let mut checksum = checksum::Adler32Checksum::new();
+    compress_data_dynamic(input, dictionary, &mut writer, &mut checksum, level)
+        .expect("Write error when writing compressed data!");
+
+    let hash = checksum.current_hash();
+
+    writer.write_u32::<BigEndian>(hash).expect("Write error when writing checksum!");
+    writer.into_inner()
+}
+
+/// Compress the given slice of bytes with DEFLATE compression, including a gzip header and
+/// trailer, using the default `GzBuilder` options (no file name or comment, mtime `0`).
+///
+/// Returns a Vec<u8> of the compressed data.
+///
+/// Requires the `gzip` feature.
+///
+/// # Examples
+///
+/// ```
+/// use deflate::deflate_bytes_gzip;
+/// let data = b"This is some test data";
+/// let compressed_data = deflate_bytes_gzip(data);
+/// # let _ = compressed_data;
+/// ```
+#[cfg(feature = "gzip")]
+pub fn deflate_bytes_gzip(input: &[u8]) -> Vec<u8> {
+    deflate_bytes_gzip_with_options(input, &gzip::GzBuilder::new())
+}
+
+/// As `deflate_bytes_gzip`, but with file name/comment/mtime set via a `GzBuilder`.
+#[cfg(feature = "gzip")]
+pub fn deflate_bytes_gzip_with_options(input: &[u8], options: &gzip::GzBuilder) -> Vec<u8> {
+    deflate_bytes_gzip_conf(input, options, Compression::default())
+}
+
+/// As `deflate_bytes_gzip_with_options`, but with the compression level/effort set explicitly.
+#[cfg(feature = "gzip")]
+pub fn deflate_bytes_gzip_conf(input: &[u8], options: &gzip::GzBuilder, level: Compression) -> Vec<u8> {
+    let mut writer = Cursor::new(Vec::with_capacity(input.len() / 3));
+    options.write_header(&mut writer, level)
+        .expect("Write error when writing gzip header!");
+
+    let mut checksum = checksum::Crc32Checksum::new();
+    compress_data_dynamic(input, &[], &mut writer, &mut checksum, level)
+        .expect("Write error when writing compressed data!");
+
+    gzip::write_trailer(&mut writer, checksum.current_hash(), input.len() as u64)
+        .expect("Write error when writing gzip trailer!");
+    writer.into_inner()
+}
+
 #[cfg(test)]
 mod test {
     use stored_block::compress_data_stored;
@@ -299,6 +450,17 @@ mod test {
         assert_eq!(test_data, result);
     }
 
+    #[test]
+    fn test_compression_none_splits_long_input_into_stored_blocks() {
+        // A single stored block can only hold up to 65535 bytes (its length is a 16-bit field);
+        // `Compression::none()` needs to split longer input into several, rather than handing the
+        // whole thing to `compress_block_stored` at once.
+        let test_data = vec![7u8; 100_000];
+        let compressed = deflate_bytes_conf(&test_data, Compression::none());
+        let result = decompress_to_end(&compressed);
+        assert_eq!(test_data, result);
+    }
+
     #[test]
     fn test_no_compression_multiple_chunks() {
         let test_data = vec![32u8; 40000];
@@ -447,6 +609,31 @@ mod test {
         assert_eq!(&test_data, result.as_slice());
     }
 
+    #[test]
+    fn test_zlib_dict_sets_fdict_flag() {
+        let dictionary = b"Hello, zlib!";
+        let test_data = b"Hello, zlib! Hello, zlib! Hello, zlib!";
+        let compressed = deflate_bytes_zlib_dict(test_data, dictionary);
+        assert_eq!(compressed[1] & (1 << 5), 1 << 5);
+    }
+
+    #[test]
+    fn test_zlib_dict_improves_compression() {
+        // `dictionary` has little internal repetition (only short sequences such as "he " recur),
+        // so without it most of the prefix has to be written out as literals; with it, the prefix
+        // collapses into a single back-reference.
+        let dictionary =
+            b"The quick brown fox jumps over the lazy dog. Pack my box with five dozen liquor \
+              jugs.".to_vec();
+        let mut test_data = dictionary.clone();
+        test_data.extend_from_slice(b" Just kidding, that's the whole sentence.");
+
+        let without_dict = deflate_bytes_zlib(&test_data);
+        let with_dict = deflate_bytes_zlib_dict(&test_data, &dictionary);
+
+        assert!(with_dict.len() < without_dict.len());
+    }
+
     #[test]
     fn test_zlib_last_block() {
         let mut test_data = vec![22; 32768];
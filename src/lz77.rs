@@ -0,0 +1,565 @@
+//! LZ77 compression: finds repeated sequences in the input and replaces them with
+//! (length, distance) back-references into the already-seen data, leaving everything else as
+//! literal bytes. The resulting stream of symbols is later huffman-coded by `encoder_state`.
+
+use std::cmp;
+
+use chained_hash_table::{ChainedHashTable, WINDOW_SIZE};
+use checksum::{NoChecksum, RollingChecksum};
+use compression_options::{Compression, CompressionOptions};
+use output_writer::Writer;
+// Re-exported since `LDPair` is conceptually LZ77's symbol type, even though `output_writer` is
+// where the `Writer` sinks that consume it live.
+pub use output_writer::LDPair;
+
+/// The shortest sequence of bytes that's worth encoding as a back-reference rather than as
+/// literals.
+pub const MIN_MATCH: usize = 3;
+/// The longest single back-reference the format supports.
+pub const MAX_MATCH: usize = 258;
+/// How much input a single call to `lz77_compress_block` consumes at most, except that a match
+/// starting before this limit can run up to `MAX_MATCH - 1` bytes past it. In practice,
+/// `should_split_block`'s cost-based heuristic usually closes a block well before this; it mainly
+/// exists as a backstop for data whose statistics never drift enough to trigger a split.
+pub const MAX_BLOCK_LENGTH: usize = 1 << 16;
+
+/// How many symbols to let a block accumulate between checks of whether splitting it here would
+/// pay for itself. Checking on every symbol would be needless overhead, since the decision only
+/// meaningfully changes as the data's statistics drift.
+const SPLIT_CHECK_INTERVAL: u64 = 200;
+
+/// How much higher the marginal bit cost of the symbols written since the last check has to be,
+/// relative to the block's average cost per symbol so far, before it's worth closing the block:
+/// splitting always gives up some ground to a fresh header, so a small drift isn't enough to make
+/// it pay for itself.
+const SPLIT_COST_FACTOR: f64 = 1.2;
+
+/// Whether `writer`'s block should be closed right now. `last_check` is updated in place to the
+/// latest `(symbol count, estimated cost in bits)` snapshot every time this actually checks (as
+/// opposed to skipping because too few symbols have been written since the last check).
+///
+/// The idea: if the bits spent on symbols written since `last_check` (the marginal cost) are
+/// notably more per symbol than the block has averaged overall, the combined huffman tree is
+/// fitting recent data poorly, and a fresh block (with a tree of its own) likely does better.
+fn should_split_block<W: Writer>(writer: &W, last_check: &mut (u64, u64)) -> bool {
+    let (symbol_count, cost_bits) = match writer.cost_snapshot() {
+        Some(snapshot) => snapshot,
+        None => return false,
+    };
+    let (last_symbol_count, last_cost_bits) = *last_check;
+    if symbol_count < last_symbol_count + SPLIT_CHECK_INTERVAL {
+        return false;
+    }
+
+    let marginal_symbols = symbol_count - last_symbol_count;
+    let marginal_cost = cost_bits.saturating_sub(last_cost_bits);
+    let average_rate = cost_bits as f64 / symbol_count as f64;
+    let marginal_rate = marginal_cost as f64 / marginal_symbols as f64;
+
+    *last_check = (symbol_count, cost_bits);
+    last_symbol_count > 0 && marginal_rate > average_rate * SPLIT_COST_FACTOR
+}
+
+/// State that needs to persist across repeated calls to `lz77_compress_block`: how far into the
+/// input we've gotten, and the hash chains used to find matches in the already-seen data.
+pub struct LZ77State {
+    // Counted from the start of the dictionary (if any), not the start of the real input; see
+    // `position()`.
+    position: usize,
+    dictionary_length: usize,
+    hash_table: ChainedHashTable,
+    is_last_block: bool,
+    options: CompressionOptions,
+}
+
+impl LZ77State {
+    pub fn new(data: &[u8], options: CompressionOptions) -> LZ77State {
+        LZ77State::with_dictionary(data, &[], options)
+    }
+
+    /// An `LZ77State` whose hash chains are pre-filled with a preset dictionary, so the very
+    /// first bytes of real input can already reference it. Matches `InputBuffer`'s truncation of
+    /// `dictionary` to its last `WINDOW_SIZE` bytes, since that's all a match could ever reach
+    /// back into anyway.
+    pub fn with_dictionary(data: &[u8], dictionary: &[u8], options: CompressionOptions) -> LZ77State {
+        let dictionary_length = cmp::min(dictionary.len(), WINDOW_SIZE);
+        LZ77State {
+            position: dictionary_length,
+            dictionary_length: dictionary_length,
+            hash_table: ChainedHashTable::from_dictionary(dictionary),
+            is_last_block: data.is_empty(),
+            options: options,
+        }
+    }
+
+    /// Whether the most recent call to `lz77_compress_block` consumed the rest of the input.
+    pub fn is_last_block(&self) -> bool {
+        self.is_last_block
+    }
+
+    /// How many bytes of the real (non-dictionary) input have been consumed so far. Lets callers
+    /// that feed data in incrementally (see `compress::DeflateEncoder`) tell how much of what
+    /// they've buffered is still waiting to be compressed.
+    pub fn position(&self) -> usize {
+        self.position - self.dictionary_length
+    }
+}
+
+/// A cursor over the data being compressed, in front of an optional preset dictionary. Matching
+/// code addresses both through a single position space, with the dictionary (if any) occupying
+/// positions `0..dictionary.len()` and the real input starting right after it; this lets matches
+/// reference dictionary bytes exactly like any other already-seen data, without the dictionary
+/// ever being written out itself.
+///
+/// `offset` lets a caller that has physically dropped an already-consumed, out-of-window prefix
+/// off the front of `data` (see `compress::DeflateEncoder`) keep using the same, ever-increasing
+/// `LZ77State::position` numbering: position `offset + dictionary.len()` still addresses
+/// `data[0]`, even though that position is no longer `0`.
+pub struct InputBuffer<'a> {
+    dictionary: &'a [u8],
+    data: &'a [u8],
+    offset: usize,
+}
+
+pub fn create_buffer(data: &[u8]) -> InputBuffer {
+    InputBuffer {
+        dictionary: &[],
+        data: data,
+        offset: 0,
+    }
+}
+
+/// As `create_buffer`, but with `data` preceded by (up to the last `WINDOW_SIZE` bytes of) a
+/// preset dictionary.
+pub fn create_buffer_with_dictionary<'a>(data: &'a [u8], dictionary: &'a [u8]) -> InputBuffer<'a> {
+    create_buffer_with_dictionary_at_offset(data, dictionary, 0)
+}
+
+/// As `create_buffer_with_dictionary`, but for a `data` slice whose first `offset` bytes have
+/// already been consumed and physically dropped by the caller, so that the position numbering
+/// built up before the drop still addresses the right bytes.
+pub fn create_buffer_with_dictionary_at_offset<'a>(data: &'a [u8],
+                                                    dictionary: &'a [u8],
+                                                    offset: usize)
+                                                    -> InputBuffer<'a> {
+    let start = dictionary.len().saturating_sub(WINDOW_SIZE);
+    InputBuffer {
+        dictionary: &dictionary[start..],
+        data: data,
+        offset: offset,
+    }
+}
+
+impl<'a> InputBuffer<'a> {
+    fn len(&self) -> usize {
+        self.offset + self.dictionary.len() + self.data.len()
+    }
+
+    fn get(&self, position: usize) -> u8 {
+        if position < self.dictionary.len() {
+            self.dictionary[position]
+        } else {
+            self.data[position - self.dictionary.len() - self.offset]
+        }
+    }
+}
+
+/// Search the hash chain at `position` for the longest match, returning `(length, distance)` if
+/// one of at least `MIN_MATCH` bytes was found. `options` bounds how hard to look: at most
+/// `max_chain_length` candidates are examined (halved once a `good_match_length`-long match has
+/// been found), and a match of at least `nice_match_length` ends the search immediately.
+fn find_match(buffer: &InputBuffer,
+               position: usize,
+               hash_table: &ChainedHashTable,
+               options: &CompressionOptions)
+               -> Option<(usize, usize)> {
+    if position + MIN_MATCH > buffer.len() {
+        return None;
+    }
+
+    let hash = hash_table.get_hash(buffer.get(position), buffer.get(position + 1), buffer.get(position + 2));
+    let max_match = cmp::min(MAX_MATCH, buffer.len() - position);
+
+    let mut best_length = 0;
+    let mut best_distance = 0;
+    let mut current = hash_table.get_head(hash);
+    let mut chain_length = 0;
+    let mut max_chain_length = options.max_chain_length as usize;
+
+    while let Some(candidate) = current {
+        if candidate >= position || position - candidate > WINDOW_SIZE {
+            break;
+        }
+        chain_length += 1;
+        if chain_length > max_chain_length {
+            break;
+        }
+
+        let length = (0..max_match)
+            .take_while(|&k| buffer.get(candidate + k) == buffer.get(position + k))
+            .count();
+
+        if length > best_length {
+            best_length = length;
+            best_distance = position - candidate;
+            if length >= max_match || length >= options.nice_match_length as usize {
+                break;
+            }
+            if length >= options.good_match_length as usize {
+                max_chain_length /= 2;
+            }
+        }
+
+        current = hash_table.get_prev(candidate);
+    }
+
+    if best_length >= MIN_MATCH {
+        Some((best_length, best_distance))
+    } else {
+        None
+    }
+}
+
+/// Insert the 3-byte sequence starting at `position` into the hash chain, if there's enough data
+/// left for one.
+fn insert_hash(buffer: &InputBuffer, position: usize, hash_table: &mut ChainedHashTable) {
+    if position + MIN_MATCH <= buffer.len() {
+        hash_table.add_hash_value(position,
+                                   buffer.get(position),
+                                   buffer.get(position + 1),
+                                   buffer.get(position + 2));
+    }
+}
+
+/// Commit to a match found earlier and deferred by lazy matching: write it out, insert the hash
+/// chain entries for the bytes it covers that haven't been inserted yet (`state.position` and
+/// `state.position + 1` already have been, by the caller), and skip `state.position` past it.
+fn emit_deferred_match<W: Writer>(buffer: &InputBuffer,
+                                   state: &mut LZ77State,
+                                   writer: &mut W,
+                                   length: usize,
+                                   distance: usize) {
+    for i in (state.position + 1)..(state.position + length - 1) {
+        insert_hash(buffer, i, &mut state.hash_table);
+    }
+    writer.write_length_distance(length as u16, distance as u16);
+    state.position += length - 1;
+}
+
+/// Compress up to `MAX_BLOCK_LENGTH` bytes of `buffer` (plus the tail of a match that starts
+/// before that limit), starting from wherever `state` left off, writing the resulting literals and
+/// length/distance pairs (plus a trailing end-of-block marker) to `writer`. The checksum argument
+/// is not updated here; callers keep their container format's checksum up to date themselves.
+///
+/// The block may end earlier than `MAX_BLOCK_LENGTH` if `writer` reports (via
+/// `Writer::cost_snapshot`) that the data's statistics have drifted enough that starting a fresh
+/// block here would be cheaper overall; see `should_split_block`. Callers drive this in a loop
+/// (checking `LZ77State::is_last_block`) and are expected to treat every call as producing exactly
+/// one block, choosing that block's `BType` based on what was actually written to `writer`.
+pub fn lz77_compress_block<W: Writer, RC: RollingChecksum>(buffer: &InputBuffer,
+                                                            state: &mut LZ77State,
+                                                            writer: &mut W,
+                                                            _checksum: &mut RC) {
+    let block_end = cmp::min(state.position + MAX_BLOCK_LENGTH, buffer.len());
+    let lazy_matching = state.options.max_lazy_match > 0;
+    let mut split_check = (0u64, 0u64);
+
+    // A match found at `state.position - 1` that hasn't been emitted yet, because we're waiting
+    // to see whether `state.position` has a strictly longer one.
+    let mut deferred_match: Option<(usize, usize)> = None;
+
+    while state.position < block_end {
+        let found_match = find_match(buffer, state.position, &state.hash_table, &state.options);
+        insert_hash(buffer, state.position, &mut state.hash_table);
+
+        let emitted = if !lazy_matching {
+            match found_match {
+                Some((length, distance)) => {
+                    for i in (state.position + 1)..(state.position + length) {
+                        insert_hash(buffer, i, &mut state.hash_table);
+                    }
+                    writer.write_length_distance(length as u16, distance as u16);
+                    state.position += length;
+                }
+                None => {
+                    writer.write_literal(buffer.get(state.position));
+                    state.position += 1;
+                }
+            }
+            true
+        } else {
+            match deferred_match.take() {
+                Some((prev_length, prev_distance)) => {
+                    let found_length = found_match.map_or(0, |(length, _)| length);
+                    if found_length > prev_length {
+                        // The byte we held back turned out not to be worth matching from: emit it
+                        // as a literal and defer the longer match we just found instead.
+                        writer.write_literal(buffer.get(state.position - 1));
+                        deferred_match = found_match;
+                        state.position += 1;
+                        true
+                    } else {
+                        emit_deferred_match(buffer, state, writer, prev_length, prev_distance);
+                        true
+                    }
+                }
+                None => {
+                    match found_match {
+                        Some((length, distance)) if length >= state.options.max_lazy_match as usize => {
+                            // Already long enough that looking one position further ahead isn't
+                            // worth it: take it immediately instead of deferring it.
+                            for i in (state.position + 1)..(state.position + length) {
+                                insert_hash(buffer, i, &mut state.hash_table);
+                            }
+                            writer.write_length_distance(length as u16, distance as u16);
+                            state.position += length;
+                            true
+                        }
+                        Some(m) => {
+                            deferred_match = Some(m);
+                            state.position += 1;
+                            false
+                        }
+                        None => {
+                            writer.write_literal(buffer.get(state.position));
+                            state.position += 1;
+                            true
+                        }
+                    }
+                }
+            }
+        };
+
+        if emitted && should_split_block(writer, &mut split_check) {
+            break;
+        }
+    }
+
+    if let Some((length, distance)) = deferred_match {
+        // The only way to get here with a deferred match still pending is the loop above ending
+        // (block_end reached, or a split) right after choosing to defer it, which bumped
+        // `state.position` without giving the loop another iteration to insert its hash.
+        // `emit_deferred_match` assumes that's already been done, so do it here first.
+        insert_hash(buffer, state.position, &mut state.hash_table);
+        emit_deferred_match(buffer, state, writer, length, distance);
+    }
+
+    writer.write_end_of_block();
+    state.is_last_block = state.position >= buffer.len();
+}
+
+/// A `Writer` that just collects the produced symbols, used by tests that want to inspect or
+/// re-encode the raw LZ77 output directly.
+struct VecWriter {
+    pairs: Vec<LDPair>,
+}
+
+impl Writer for VecWriter {
+    fn write_literal(&mut self, value: u8) {
+        self.pairs.push(LDPair::Literal(value));
+    }
+
+    fn write_length_distance(&mut self, length: u16, distance: u16) {
+        self.pairs.push(LDPair::LengthDistance {
+            length: length,
+            distance: distance,
+        });
+    }
+
+    fn write_end_of_block(&mut self) {
+        self.pairs.push(LDPair::EndOfBlock);
+    }
+}
+
+/// Compress the whole of `data` in one go, returning the raw sequence of LZ77 symbols (including
+/// a final `LDPair::EndOfBlock`), or `None` if `data` is empty. Mainly used by tests that want to
+/// drive `encoder_state` directly without going through `compress_data_dynamic`.
+pub fn lz77_compress(data: &[u8]) -> Option<Vec<LDPair>> {
+    if data.is_empty() {
+        return None;
+    }
+
+    let mut state = LZ77State::new(data, Compression::default().to_options());
+    let buffer = create_buffer(data);
+    let mut writer = VecWriter { pairs: Vec::new() };
+    let mut checksum = NoChecksum::new();
+
+    while !state.is_last_block() {
+        lz77_compress_block(&buffer, &mut state, &mut writer, &mut checksum);
+    }
+
+    Some(writer.pairs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lz77_compress_finds_repeat() {
+        let data = b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+        let compressed = lz77_compress(data).unwrap();
+        let has_match = compressed.iter().any(|ld| {
+            match *ld {
+                LDPair::LengthDistance { .. } => true,
+                _ => false,
+            }
+        });
+        assert!(has_match);
+    }
+
+    #[test]
+    fn test_lz77_compress_empty() {
+        assert!(lz77_compress(b"").is_none());
+    }
+
+    fn compress_with(data: &[u8], options: CompressionOptions) -> Vec<LDPair> {
+        let mut state = LZ77State::new(data, options);
+        let buffer = create_buffer(data);
+        let mut writer = VecWriter { pairs: Vec::new() };
+        let mut checksum = NoChecksum::new();
+        while !state.is_last_block() {
+            lz77_compress_block(&buffer, &mut state, &mut writer, &mut checksum);
+        }
+        writer.pairs
+    }
+
+    #[test]
+    fn test_lazy_matching_finds_longer_match() {
+        // Crafted so that the greedy matcher commits to a length-3 match one byte too early,
+        // while deferring that decision by one position (lazy matching) finds a length-4 match.
+        let data = b"bddbccbcacbccba";
+        let greedy_options = CompressionOptions {
+            max_chain_length: 128,
+            good_match_length: 32,
+            nice_match_length: 128,
+            max_lazy_match: 0,
+        };
+        let greedy = compress_with(data, greedy_options);
+        let lazy = compress_with(data, Compression::default().to_options());
+
+        let longest_match = |pairs: &[LDPair]| {
+            pairs.iter()
+                .filter_map(|ld| match *ld {
+                    LDPair::LengthDistance { length, .. } => Some(length),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0)
+        };
+
+        assert_eq!(longest_match(&greedy), 3);
+        assert_eq!(longest_match(&lazy), 4);
+        assert!(lazy.len() < greedy.len());
+    }
+
+    #[test]
+    fn test_lazy_matching_short_circuits_past_threshold() {
+        // Same data as `test_lazy_matching_finds_longer_match`, but with `max_lazy_match` lowered
+        // to the greedy match's own length: that match is now "good enough" on its own, so lazy
+        // matching should take it immediately rather than paying for the extra lookahead that
+        // would otherwise find the longer one.
+        let data = b"bddbccbcacbccba";
+        let mut capped_options = Compression::default().to_options();
+        capped_options.max_lazy_match = 3;
+        let capped = compress_with(data, capped_options);
+
+        let longest_match = |pairs: &[LDPair]| {
+            pairs.iter()
+                .filter_map(|ld| match *ld {
+                    LDPair::LengthDistance { length, .. } => Some(length),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0)
+        };
+
+        assert_eq!(longest_match(&capped), 3);
+    }
+
+    /// A `Writer` whose `cost_snapshot` is set directly, so `should_split_block`'s threshold logic
+    /// can be tested without driving a full LZ77 search.
+    struct FakeCostWriter {
+        symbol_count: u64,
+        cost_bits: u64,
+    }
+
+    impl Writer for FakeCostWriter {
+        fn write_literal(&mut self, _value: u8) {}
+        fn write_length_distance(&mut self, _length: u16, _distance: u16) {}
+        fn write_end_of_block(&mut self) {}
+        fn cost_snapshot(&self) -> Option<(u64, u64)> {
+            Some((self.symbol_count, self.cost_bits))
+        }
+    }
+
+    #[test]
+    fn test_should_split_block_waits_for_check_interval() {
+        let writer = FakeCostWriter {
+            symbol_count: 50,
+            cost_bits: 500,
+        };
+        let mut last_check = (0u64, 0u64);
+        assert!(!should_split_block(&writer, &mut last_check));
+    }
+
+    #[test]
+    fn test_should_split_block_triggers_on_cost_spike() {
+        let mut last_check = (0u64, 0u64);
+        // 200 symbols costing 1 bit each, on average.
+        let warmup = FakeCostWriter {
+            symbol_count: 200,
+            cost_bits: 200,
+        };
+        assert!(!should_split_block(&warmup, &mut last_check));
+        assert_eq!(last_check, (200, 200));
+
+        // 400 more symbols costing 10 bits each: a sharp jump over the 1 bit/symbol average so far.
+        let spike = FakeCostWriter {
+            symbol_count: 600,
+            cost_bits: 200 + 400 * 10,
+        };
+        assert!(should_split_block(&spike, &mut last_check));
+    }
+
+    #[test]
+    fn test_should_split_block_ignores_mild_drift() {
+        let mut last_check = (0u64, 0u64);
+        let warmup = FakeCostWriter {
+            symbol_count: 200,
+            cost_bits: 200,
+        };
+        should_split_block(&warmup, &mut last_check);
+
+        // Another 200 symbols at the same 1 bit/symbol rate shouldn't trigger a split.
+        let mild = FakeCostWriter {
+            symbol_count: 400,
+            cost_bits: 400,
+        };
+        assert!(!should_split_block(&mild, &mut last_check));
+    }
+
+    #[test]
+    fn test_dictionary_is_matched_from_first_byte() {
+        let dictionary = b"the quick brown fox";
+        let data = b"the quick brown fox jumps";
+        let options = Compression::default().to_options();
+
+        let mut state = LZ77State::with_dictionary(data, dictionary, options);
+        assert_eq!(state.position(), 0);
+        let buffer = create_buffer_with_dictionary(data, dictionary);
+        let mut writer = VecWriter { pairs: Vec::new() };
+        let mut checksum = NoChecksum::new();
+        while !state.is_last_block() {
+            lz77_compress_block(&buffer, &mut state, &mut writer, &mut checksum);
+        }
+
+        // The very first symbol should already be a back-reference into the dictionary, rather
+        // than a run of literals.
+        let first_is_match = match writer.pairs[0] {
+            LDPair::LengthDistance { .. } => true,
+            _ => false,
+        };
+        assert!(first_is_match);
+    }
+}
@@ -0,0 +1,206 @@
+//! Sinks that the LZ77 compressor writes its literal/length/distance symbols to.
+//!
+//! The LZ77 step doesn't know yet which `BType` the surrounding block will use, so it writes
+//! through this trait instead of talking to an `EncoderState` directly. `DynamicWriter` buffers
+//! the symbols produced for a single block along with the frequency tables needed to build the
+//! huffman codes for it.
+
+use huffman_table::{distance_code, length_code, FIXED_CODE_LENGTHS, NUM_DISTANCE_CODES,
+                    NUM_LITERALS_AND_LENGTHS};
+
+/// A single LZ77-compressed symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LDPair {
+    Literal(u8),
+    LengthDistance { length: u16, distance: u16 },
+    EndOfBlock,
+}
+
+/// Rough overhead, in bits, of a `BType::DynamicHuffman` block's header (RFC 1951 section 3.2.7):
+/// the HLIT/HDIST/HCLEN counts, the code-length alphabet's own code lengths, and the run-length
+/// encoded lengths for the literal/length and distance tables. The real cost varies with how
+/// skewed those tables are, but ~20 bytes is typical.
+const DYNAMIC_HEADER_OVERHEAD_BITS: u64 = 20 * 8;
+
+/// A sink LZ77 symbols are written to as they're produced.
+pub trait Writer {
+    fn write_literal(&mut self, value: u8);
+    fn write_length_distance(&mut self, length: u16, distance: u16);
+    fn write_end_of_block(&mut self);
+
+    /// A `(symbol count, estimated dynamic-block cost in bits)` snapshot of everything written to
+    /// this block so far, used by `lz77::lz77_compress_block`'s block-splitting heuristic.
+    /// `None` for writers that don't track the frequency information needed (e.g. `VecWriter`,
+    /// used by tests that just want the raw LZ77 symbol stream).
+    fn cost_snapshot(&self) -> Option<(u64, u64)> {
+        None
+    }
+}
+
+/// Buffers the symbols for a block, plus the literal/length and distance symbol frequencies
+/// needed to generate the huffman codes the block will be encoded with.
+pub struct DynamicWriter {
+    buffer: Vec<LDPair>,
+    l_freqs: [u32; NUM_LITERALS_AND_LENGTHS],
+    d_freqs: [u32; NUM_DISTANCE_CODES],
+    // Bits spent on length/distance extra bits, which cost the same no matter which `BType` ends
+    // up encoding the symbols, so they're tracked directly rather than re-derived from the
+    // frequency tables.
+    extra_bits: u64,
+}
+
+impl DynamicWriter {
+    pub fn new() -> DynamicWriter {
+        DynamicWriter {
+            buffer: Vec::new(),
+            l_freqs: [0; NUM_LITERALS_AND_LENGTHS],
+            d_freqs: [0; NUM_DISTANCE_CODES],
+            extra_bits: 0,
+        }
+    }
+
+    pub fn get_frequencies(&self) -> (&[u32], &[u32]) {
+        (&self.l_freqs, &self.d_freqs)
+    }
+
+    pub fn get_buffer(&self) -> &[LDPair] {
+        &self.buffer
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.l_freqs = [0; NUM_LITERALS_AND_LENGTHS];
+        self.d_freqs = [0; NUM_DISTANCE_CODES];
+        self.extra_bits = 0;
+    }
+
+    /// How many symbols (literals and matches, not counting the end-of-block marker) have been
+    /// written to this block so far.
+    pub fn symbol_count(&self) -> u64 {
+        self.l_freqs.iter().map(|&f| f as u64).sum::<u64>() - self.l_freqs[256] as u64
+    }
+
+    /// Estimate, in bits, what encoding everything written to this block so far would cost as a
+    /// `BType::DynamicHuffman` block: the Shannon entropy of the literal/length and distance
+    /// frequency tables (a lower bound on what an optimal prefix code, which huffman coding
+    /// approximates, needs), plus the length/distance extra bits and the block header overhead.
+    pub fn estimated_dynamic_cost_bits(&self) -> u64 {
+        entropy_bits(&self.l_freqs) + entropy_bits(&self.d_freqs) + self.extra_bits +
+        DYNAMIC_HEADER_OVERHEAD_BITS
+    }
+
+    /// As `estimated_dynamic_cost_bits`, but for `BType::FixedHuffman`, whose code lengths are
+    /// fixed by the spec rather than chosen per block, so there's no header to account for.
+    pub fn estimated_fixed_cost_bits(&self) -> u64 {
+        let l_bits: u64 = self.l_freqs
+            .iter()
+            .zip(FIXED_CODE_LENGTHS.iter())
+            .map(|(&freq, &code_length)| freq as u64 * code_length as u64)
+            .sum();
+        // Every distance code is 5 bits under the fixed table (`FIXED_CODE_LENGTHS_DISTANCE`).
+        let d_bits: u64 = self.d_freqs.iter().map(|&freq| freq as u64 * 5).sum();
+        l_bits + d_bits + self.extra_bits
+    }
+}
+
+impl Writer for DynamicWriter {
+    fn write_literal(&mut self, value: u8) {
+        self.buffer.push(LDPair::Literal(value));
+        self.l_freqs[value as usize] += 1;
+    }
+
+    fn write_length_distance(&mut self, length: u16, distance: u16) {
+        self.buffer.push(LDPair::LengthDistance {
+            length: length,
+            distance: distance,
+        });
+        let (l_code, l_extra_bits, _) = length_code(length);
+        self.l_freqs[l_code as usize] += 1;
+        let (d_code, d_extra_bits, _) = distance_code(distance);
+        self.d_freqs[d_code as usize] += 1;
+        self.extra_bits += l_extra_bits as u64 + d_extra_bits as u64;
+    }
+
+    fn write_end_of_block(&mut self) {
+        self.buffer.push(LDPair::EndOfBlock);
+        self.l_freqs[256] += 1;
+    }
+
+    fn cost_snapshot(&self) -> Option<(u64, u64)> {
+        Some((self.symbol_count(), self.estimated_dynamic_cost_bits()))
+    }
+}
+
+/// The Shannon entropy of `freqs`, in bits: a lower bound on how many bits an optimal prefix code
+/// needs to encode every symbol counted in it.
+fn entropy_bits(freqs: &[u32]) -> u64 {
+    let total: u64 = freqs.iter().map(|&f| f as u64).sum();
+    if total == 0 {
+        return 0;
+    }
+    let bits: f64 = freqs.iter()
+        .filter(|&&f| f > 0)
+        .map(|&f| {
+            let p = f as f64 / total as f64;
+            f as f64 * -p.log2()
+        })
+        .sum();
+    bits.ceil() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frequencies() {
+        let mut writer = DynamicWriter::new();
+        writer.write_literal(65);
+        writer.write_length_distance(4, 1);
+        writer.write_end_of_block();
+
+        let (l_freqs, d_freqs) = writer.get_frequencies();
+        assert_eq!(l_freqs[65], 1);
+        assert_eq!(l_freqs[256], 1);
+        assert_eq!(d_freqs[0], 1);
+        assert_eq!(writer.get_buffer().len(), 3);
+    }
+
+    #[test]
+    fn test_symbol_count_excludes_end_of_block() {
+        let mut writer = DynamicWriter::new();
+        writer.write_literal(1);
+        writer.write_literal(2);
+        writer.write_length_distance(4, 1);
+        assert_eq!(writer.symbol_count(), 3);
+        writer.write_end_of_block();
+        assert_eq!(writer.symbol_count(), 3);
+    }
+
+    #[test]
+    fn test_skewed_frequencies_cost_fewer_bits_than_uniform() {
+        // A block of all-identical literals should be estimated as cheaper to huffman-code than
+        // one where every byte value is equally likely.
+        let mut skewed = DynamicWriter::new();
+        for _ in 0..100 {
+            skewed.write_literal(65);
+        }
+
+        let mut uniform = DynamicWriter::new();
+        for i in 0..100 {
+            uniform.write_literal((i % 256) as u8);
+        }
+
+        assert!(skewed.estimated_dynamic_cost_bits() < uniform.estimated_dynamic_cost_bits());
+    }
+
+    #[test]
+    fn test_fixed_cost_matches_known_bit_width() {
+        let mut writer = DynamicWriter::new();
+        // Literal 65 is 8 bits under the fixed table; a length-4/distance-1 match has no extra
+        // bits, a 7-bit length code (length codes 256-279 get 7 bits) and a 5-bit distance code.
+        writer.write_literal(65);
+        writer.write_length_distance(4, 1);
+        assert_eq!(writer.estimated_fixed_cost_bits(), 8 + 7 + 5);
+    }
+}
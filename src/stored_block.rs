@@ -0,0 +1,55 @@
+//! Writing of `BType::NoCompression` ("stored") blocks: the input copied through verbatim, framed
+//! by a length and its one's complement so a decoder can tell it apart from a truncated stream.
+
+use std::io::{self, Cursor};
+
+use bitstream::{BitWriter, LsbWriter};
+
+/// The 3-bit block header (BFINAL=1, BTYPE=00) for a final stored block, written LSB-first.
+pub const STORED_FIRST_BYTE_FINAL: u8 = 0b001;
+/// The 3-bit block header (BFINAL=0, BTYPE=00) for a non-final stored block.
+pub const STORED_FIRST_BYTE: u8 = 0b000;
+
+/// The largest amount of data a single stored block can hold, since its length is a 16-bit field.
+pub(crate) const MAX_STORED_BLOCK_LENGTH: usize = 65535;
+
+/// Write `data` as the body of a stored block: a little-endian length, its one's complement, and
+/// then the raw bytes. The caller must have already written the block header and flushed the
+/// writer to a byte boundary, since stored block contents aren't bit-packed.
+pub fn compress_block_stored<W: BitWriter>(data: &[u8], writer: &mut W) -> io::Result<()> {
+    assert!(data.len() <= MAX_STORED_BLOCK_LENGTH,
+            "Stored blocks longer than 65535 bytes need to be split into several blocks by the \
+             caller");
+
+    let len = data.len() as u16;
+    try!(writer.write_bits(len, 16));
+    try!(writer.write_bits(!len, 16));
+    for &byte in data {
+        try!(writer.write_bits(byte as u16, 8));
+    }
+    Ok(())
+}
+
+/// Compress `data` as a single, complete stored-block DEFLATE stream. Used directly by tests that
+/// want to check the stored-block path without going through `compress_data_dynamic`.
+pub fn compress_data_stored(data: &[u8]) -> Vec<u8> {
+    let mut writer = LsbWriter::new(Cursor::new(Vec::with_capacity(data.len() + 8)));
+    writer.write_bits(STORED_FIRST_BYTE_FINAL as u16, 3).expect("Write error!");
+    writer.flush().expect("Write error!");
+    compress_block_stored(data, &mut writer).expect("Write error!");
+    writer.flush().expect("Write error!");
+    writer.into_inner().into_inner()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compress_data_stored_roundtrippable_length() {
+        let data = vec![5u8; 10];
+        let compressed = compress_data_stored(&data);
+        // 3 header bits padded to a byte, plus 4 bytes of length/nlen, plus the data itself.
+        assert_eq!(compressed.len(), 1 + 4 + data.len());
+    }
+}
@@ -0,0 +1,115 @@
+//! Writing of the zlib header (RFC 1950 section 2.2) wrapped around a raw DEFLATE stream.
+
+use std::io::{self, Write};
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+use compression_options::Compression;
+
+/// The CM (compression method) field of the CMF byte: DEFLATE. Together with a 32K window
+/// (`CINFO_32K_WINDOW`) it is the only combination this crate produces.
+const CM_DEFLATE: u8 = 8;
+const CINFO_32K_WINDOW: u8 = 7;
+
+/// FLG bit signaling that a preset dictionary's Adler32 follows the 2-byte header.
+const FDICT: u8 = 1 << 5;
+
+/// A hint, carried in the zlib header, about how much effort went into compressing the stream.
+/// This doesn't affect decoding; it's informational only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionLevel {
+    Fastest,
+    Fast,
+    Default,
+    Maximum,
+}
+
+impl CompressionLevel {
+    fn flevel_bits(self) -> u8 {
+        match self {
+            CompressionLevel::Fastest => 0,
+            CompressionLevel::Fast => 1,
+            CompressionLevel::Default => 2,
+            CompressionLevel::Maximum => 3,
+        }
+    }
+
+    /// The header hint corresponding to an actual `Compression` effort level, so callers only
+    /// have to pick one level rather than keep the header hint and the real search effort in
+    /// sync by hand.
+    pub fn from_compression(level: Compression) -> CompressionLevel {
+        match level.level() {
+            0 | 1 => CompressionLevel::Fastest,
+            2...5 => CompressionLevel::Fast,
+            6...8 => CompressionLevel::Default,
+            _ => CompressionLevel::Maximum,
+        }
+    }
+}
+
+/// Write the 2-byte zlib header: CMF (compression method and window size) and FLG (compression
+/// level hint, optional preset-dictionary flag and a check bits field chosen so the two header
+/// bytes, read as one big-endian `u16`, are a multiple of 31).
+pub fn write_zlib_header<W: Write>(writer: &mut W, level: CompressionLevel) -> io::Result<()> {
+    let (cmf, flg) = header_bytes(level, false);
+    try!(writer.write_u8(cmf));
+    writer.write_u8(flg)
+}
+
+/// As `write_zlib_header`, but for a stream compressed against a preset dictionary: sets the
+/// `FDICT` flag and follows the header with the dictionary's Adler32, big-endian, as required by
+/// RFC 1950 section 2.2.
+pub fn write_zlib_header_with_dictionary<W: Write>(writer: &mut W,
+                                                     level: CompressionLevel,
+                                                     dictionary_adler32: u32)
+                                                     -> io::Result<()> {
+    let (cmf, flg) = header_bytes(level, true);
+    try!(writer.write_u8(cmf));
+    try!(writer.write_u8(flg));
+    writer.write_u32::<BigEndian>(dictionary_adler32)
+}
+
+/// The two zlib header bytes for `level`, with the `FDICT` flag set according to `fdict`.
+fn header_bytes(level: CompressionLevel, fdict: bool) -> (u8, u8) {
+    let cmf = (CINFO_32K_WINDOW << 4) | CM_DEFLATE;
+    let mut flg = level.flevel_bits() << 6;
+    if fdict {
+        flg |= FDICT;
+    }
+    flg |= fcheck(cmf, flg);
+    (cmf, flg)
+}
+
+/// The value of the 5 FCHECK bits (the low bits of FLG) that makes `cmf * 256 + flg` a multiple
+/// of 31, given the other FLG bits are already set.
+fn fcheck(cmf: u8, flg_without_check: u8) -> u8 {
+    let remainder = ((cmf as u16) * 256 + flg_without_check as u16) % 31;
+    if remainder == 0 {
+        0
+    } else {
+        (31 - remainder) as u8
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_header_is_multiple_of_31() {
+        let mut out = Vec::new();
+        write_zlib_header(&mut out, CompressionLevel::Default).unwrap();
+        let value = (out[0] as u16) * 256 + out[1] as u16;
+        assert_eq!(value % 31, 0);
+    }
+
+    #[test]
+    fn test_header_with_dictionary_sets_fdict_and_adler() {
+        let mut out = Vec::new();
+        write_zlib_header_with_dictionary(&mut out, CompressionLevel::Default, 0x12345678).unwrap();
+        let value = (out[0] as u16) * 256 + out[1] as u16;
+        assert_eq!(value % 31, 0);
+        assert_eq!(out[1] & FDICT, FDICT);
+        assert_eq!(&out[2..6], &[0x12, 0x34, 0x56, 0x78]);
+    }
+}